@@ -0,0 +1,74 @@
+use nannou::prelude::*;
+
+use crate::canvas::Pixel;
+use crate::palette::{lerp, luminance};
+use crate::selection::Weights;
+
+/// A change applied to the color of every cell at once.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Adjustment {
+    Invert,
+    Grayscale,
+    /// Adds the amount to each channel, darkening when negative.
+    Brightness(i16),
+    /// Rounds each channel to one of this many evenly spaced levels.
+    Posterize(u8),
+    /// Replaces every color with this one.
+    Fill(Rgb8),
+}
+
+impl Adjustment {
+    /// The name the adjustment is shown under in the undo history.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Adjustment::Invert => "Invert",
+            Adjustment::Grayscale => "Grayscale",
+            Adjustment::Brightness(_) => "Brightness",
+            Adjustment::Posterize(_) => "Posterize",
+            Adjustment::Fill(_) => "Fill Canvas",
+        }
+    }
+
+    pub fn apply(&self, color: Rgb8) -> Rgb8 {
+        let channels = |f: &dyn Fn(u8) -> u8| rgb8(f(color.red), f(color.green), f(color.blue));
+        match *self {
+            Adjustment::Invert => channels(&|channel| 255 - channel),
+            Adjustment::Grayscale => {
+                let gray = luminance(color);
+                rgb8(gray, gray, gray)
+            }
+            Adjustment::Brightness(amount) => {
+                channels(&|channel| (channel as i16 + amount).clamp(0, 255) as u8)
+            }
+            Adjustment::Posterize(levels) => {
+                let steps = levels.max(2) as f32 - 1.0;
+                channels(&|channel| {
+                    ((channel as f32 / 255.0 * steps).round() / steps * 255.0).round() as u8
+                })
+            }
+            Adjustment::Fill(color) => color,
+        }
+    }
+}
+
+/// Adjusts every cell of the canvas, or only the selected ones if
+/// there is a selection. Partly selected cells are blended between
+/// their old and adjusted colors, and cells whose color is `locked`
+/// are left alone.
+pub fn adjust<F>(
+    pixels: &mut [Vec<Pixel>],
+    adjustment: Adjustment,
+    weights: Option<&Weights>,
+    locked: F,
+) where
+    F: Fn(Rgb8) -> bool,
+{
+    for (x, column) in pixels.iter_mut().enumerate() {
+        for (y, pixel) in column.iter_mut().enumerate() {
+            let weight = weights.map_or(1.0, |weights| weights[x][y]);
+            if weight > 0.0 && !locked(pixel.color) {
+                pixel.color = lerp(pixel.color, adjustment.apply(pixel.color), weight);
+            }
+        }
+    }
+}
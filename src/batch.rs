@@ -0,0 +1,111 @@
+use std::fs;
+use std::path::PathBuf;
+
+use crate::{export, import, palette};
+
+const USAGE: &str =
+    "usage: ap-drawing --batch <input dir> --out <output dir> [--grid <size>] [--palette <file>]";
+
+/// Options for converting a folder of images without opening a window.
+pub struct Batch {
+    input: PathBuf,
+    output: PathBuf,
+    grid_size: usize,
+    palette: Option<PathBuf>,
+}
+
+impl Batch {
+    /// Parses the command line, returning `None` if no arguments were given.
+    pub fn from_args<I>(args: I) -> Result<Option<Self>, String>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut args = args.into_iter().peekable();
+        if args.peek().is_none() {
+            return Ok(None);
+        }
+
+        let mut input = None;
+        let mut output = None;
+        let mut grid_size = 16;
+        let mut palette = None;
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("missing value for {arg}"))
+            };
+            match arg.as_str() {
+                "--batch" => input = Some(PathBuf::from(value()?)),
+                "--out" => output = Some(PathBuf::from(value()?)),
+                "--grid" => {
+                    grid_size = value()?
+                        .parse()
+                        .ok()
+                        .filter(|size| *size > 0)
+                        .ok_or("--grid must be a positive number")?;
+                }
+                "--palette" => palette = Some(PathBuf::from(value()?)),
+                _ => return Err(format!("unknown argument {arg}\n{USAGE}")),
+            }
+        }
+
+        match (input, output) {
+            (Some(input), Some(output)) => Ok(Some(Batch {
+                input,
+                output,
+                grid_size,
+                palette,
+            })),
+            _ => Err(USAGE.to_string()),
+        }
+    }
+
+    /// Converts every image in the input folder, reporting each file's result.
+    pub fn run(&self) -> Result<(), String> {
+        let colors = match &self.palette {
+            Some(path) => palette::read_palette(path)
+                .map_err(|e| format!("failed to read {}: {e}", path.display()))?,
+            None => Vec::new(),
+        };
+        let entries = fs::read_dir(&self.input)
+            .map_err(|e| format!("failed to read {}: {e}", self.input.display()))?;
+        fs::create_dir_all(&self.output)
+            .map_err(|e| format!("failed to create {}: {e}", self.output.display()))?;
+
+        for path in entries.filter_map(Result::ok).map(|entry| entry.path()) {
+            if !path.is_file() {
+                continue;
+            }
+
+            let out = self
+                .output
+                .join(path.file_name().unwrap())
+                .with_extension("png");
+            // Batches have always stretched images to the grid
+            let result = import::load_image(
+                &path,
+                self.grid_size,
+                self.grid_size,
+                import::Resample::Nearest,
+                import::Aspect::Stretch,
+                nannou::color::BLACK,
+            )
+            .and_then(|mut pixels| {
+                if !colors.is_empty() {
+                    for pixel in pixels.iter_mut().flatten() {
+                        pixel.color = palette::nearest(pixel.color, &colors);
+                    }
+                }
+                export::write_png(&out, &pixels, &Default::default())
+            });
+
+            match result {
+                Ok(()) => println!("ok: {} -> {}", path.display(), out.display()),
+                Err(e) => println!("failed: {}: {e}", path.display()),
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,275 @@
+use nannou::prelude::*;
+
+use crate::selection::Mask;
+
+/// Which way to mirror the canvas.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Flip {
+    /// Swaps the left and right sides.
+    Horizontal,
+    /// Swaps the top and bottom.
+    Vertical,
+}
+
+/// A single cell of the canvas, along with where it is drawn
+/// in the window.
+#[derive(Clone)]
+pub struct Pixel {
+    pub color: Rgb8,
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Default for Pixel {
+    fn default() -> Self {
+        Pixel {
+            color: BLACK,
+            x: 0.0,
+            y: 0.0,
+        }
+    }
+}
+
+/// Converts between a row of the canvas and a row of an exported
+/// image. Canvas rows count up from the bottom while image rows
+/// count down from the top, so the conversion is its own inverse.
+pub fn image_row(y: usize, height: usize) -> usize {
+    height - 1 - y
+}
+
+/// Implementation of Bresenham's line algorithm,
+/// covering both endpoints.
+pub fn calc_line_pixels(start: (i32, i32), end: (i32, i32)) -> Vec<(i32, i32)> {
+    let dx = (end.0 - start.0).abs();
+    let dy = -(end.1 - start.1).abs();
+    let step_x = (end.0 - start.0).signum();
+    let step_y = (end.1 - start.1).signum();
+    let (mut x, mut y) = start;
+    let mut err = dx + dy;
+    let mut points = Vec::new();
+
+    loop {
+        points.push((x, y));
+        if (x, y) == end {
+            break;
+        }
+
+        let err_2 = err * 2;
+        if err_2 >= dy {
+            err += dy;
+            x += step_x;
+        }
+        if err_2 <= dx {
+            err += dx;
+            y += step_y;
+        }
+    }
+
+    points
+}
+
+/// Returns the cells with no neighbor of the same color in any of
+/// the eight directions, which are often stray dots left by
+/// accident. Cells of the `background` color are never counted.
+pub fn orphans(pixels: &[Vec<Pixel>], background: Rgb8) -> Vec<(usize, usize)> {
+    let color = |x: i32, y: i32| {
+        if x < 0 || y < 0 {
+            return None;
+        }
+        pixels
+            .get(x as usize)
+            .and_then(|column| column.get(y as usize))
+            .map(|pixel| pixel.color)
+    };
+    let mut orphans = Vec::new();
+
+    for (x, column) in pixels.iter().enumerate() {
+        for (y, pixel) in column.iter().enumerate() {
+            if pixel.color == background {
+                continue;
+            }
+
+            let (x, y) = (x as i32, y as i32);
+            let alone = (-1..=1)
+                .flat_map(|dx| (-1..=1).map(move |dy| (dx, dy)))
+                .filter(|&offset| offset != (0, 0))
+                .all(|(dx, dy)| color(x + dx, y + dy) != Some(pixel.color));
+            if alone {
+                orphans.push((x as usize, y as usize));
+            }
+        }
+    }
+
+    orphans
+}
+
+/// The cells of the layer being drawn on and the operations that
+/// change them. Nothing here depends on the window or input, so
+/// drawing can be driven from tests as well as the UI.
+///
+/// Cells are stored as `pixels[x][y]`, a list of columns. `x` counts
+/// columns from the left and `y` counts rows from the bottom, so both
+/// grow in the same direction as nannou's window coordinates. Use
+/// `index` to turn a cell into indices, and `image_row` to convert
+/// rows for images, which count from the top.
+#[derive(Clone)]
+pub struct Canvas {
+    pub pixels: Vec<Vec<Pixel>>,
+}
+
+impl Canvas {
+    /// Creates a canvas `width` by `height` cells filled with `fill`.
+    pub fn new(width: usize, height: usize, fill: Rgb8) -> Self {
+        let pixel = Pixel {
+            color: fill,
+            ..Default::default()
+        };
+        Canvas {
+            pixels: vec![vec![pixel; height]; width],
+        }
+    }
+
+    /// How many cells across the canvas is.
+    pub fn width(&self) -> usize {
+        self.pixels.len()
+    }
+
+    /// How many cells tall the canvas is.
+    pub fn height(&self) -> usize {
+        self.pixels.first().map_or(0, Vec::len)
+    }
+
+    /// Returns the indices into `pixels` of the cell `x` columns from
+    /// the left and `y` rows from the bottom, or `None` if it lies
+    /// outside the canvas.
+    pub fn index(&self, x: i32, y: i32) -> Option<(usize, usize)> {
+        let (width, height) = (self.width() as i32, self.height() as i32);
+        ((0..width).contains(&x) && (0..height).contains(&y)).then_some((x as usize, y as usize))
+    }
+
+    /// The color of a cell, or `None` if it lies outside the canvas.
+    pub fn color(&self, x: i32, y: i32) -> Option<Rgb8> {
+        let (x, y) = self.index(x, y)?;
+        Some(self.pixels[x][y].color)
+    }
+
+    /// Sets a cell's color, returning whether it changed. Cells
+    /// outside the canvas are ignored.
+    pub fn paint_cell(&mut self, x: i32, y: i32, color: Rgb8) -> bool {
+        let Some((x, y)) = self.index(x, y) else {
+            return false;
+        };
+
+        let pixel = &mut self.pixels[x][y];
+        let changed = pixel.color != color;
+        pixel.color = color;
+        changed
+    }
+
+    /// Paints a straight line of cells covering both ends. `color` is
+    /// given how far along the line each cell is, from 0.0 to 1.0, and
+    /// the cell's current color, and returns its new color or `None`
+    /// to leave it alone. Returns how many cells changed.
+    pub fn stroke<F>(&mut self, start: (i32, i32), end: (i32, i32), mut color: F) -> usize
+    where
+        F: FnMut(f32, Rgb8) -> Option<Rgb8>,
+    {
+        let points = calc_line_pixels(start, end);
+        let last = (points.len() - 1).max(1) as f32;
+        let mut changed = 0;
+        for (i, (x, y)) in points.into_iter().enumerate() {
+            let Some(old) = self.color(x, y) else {
+                continue;
+            };
+            if let Some(new) = color(i as f32 / last, old) {
+                changed += self.paint_cell(x, y, new) as usize;
+            }
+        }
+        changed
+    }
+
+    /// Returns every cell connected to the given one whose color is
+    /// within `tolerance` of its color on every channel. If `mask` is
+    /// given the region never leaves the cells it selects.
+    pub fn region(
+        &self,
+        x: usize,
+        y: usize,
+        tolerance: u8,
+        mask: Option<&Mask>,
+    ) -> Vec<(usize, usize)> {
+        let pixels = &self.pixels;
+        let color = pixels[x][y].color;
+        let matches = |other: Rgb8| {
+            color.red.abs_diff(other.red) <= tolerance
+                && color.green.abs_diff(other.green) <= tolerance
+                && color.blue.abs_diff(other.blue) <= tolerance
+        };
+        let mut visited = vec![vec![false; pixels[0].len()]; pixels.len()];
+        let mut stack = vec![(x, y)];
+        let mut region = Vec::new();
+
+        while let Some((x, y)) = stack.pop() {
+            let masked = mask.is_some_and(|mask| !mask[x][y]);
+            if visited[x][y] || masked || !matches(pixels[x][y].color) {
+                continue;
+            }
+            visited[x][y] = true;
+            region.push((x, y));
+
+            if x > 0 {
+                stack.push((x - 1, y));
+            }
+            if x + 1 < pixels.len() {
+                stack.push((x + 1, y));
+            }
+            if y > 0 {
+                stack.push((x, y - 1));
+            }
+            if y + 1 < pixels[x].len() {
+                stack.push((x, y + 1));
+            }
+        }
+
+        region
+    }
+
+    /// Flood fills the cells connected to the given one that share
+    /// its color, staying inside `mask` if given. Returns how many
+    /// cells changed.
+    pub fn fill(&mut self, x: i32, y: i32, color: Rgb8, mask: Option<&Mask>) -> usize {
+        let Some((x, y)) = self.index(x, y) else {
+            return 0;
+        };
+
+        let region = self.region(x, y, 0, mask);
+        region
+            .into_iter()
+            .filter(|&(x, y)| self.paint_cell(x as i32, y as i32, color))
+            .count()
+    }
+
+    /// Mirrors the canvas, leaving each cell's position alone.
+    pub fn flip(&mut self, flip: Flip) {
+        let colors: Vec<Vec<Rgb8>> = self
+            .pixels
+            .iter()
+            .map(|column| column.iter().map(|pixel| pixel.color).collect())
+            .collect();
+        let last_x = self.width().saturating_sub(1);
+        let last_y = self.height().saturating_sub(1);
+        for (x, column) in self.pixels.iter_mut().enumerate() {
+            for (y, pixel) in column.iter_mut().enumerate() {
+                pixel.color = match flip {
+                    Flip::Horizontal => colors[last_x - x][y],
+                    Flip::Vertical => colors[x][last_y - y],
+                };
+            }
+        }
+    }
+
+    /// Replaces every cell with a blank grid `width` by `height` cells.
+    pub fn reset(&mut self, width: usize, height: usize, fill: Rgb8) {
+        *self = Canvas::new(width, height, fill);
+    }
+}
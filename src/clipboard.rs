@@ -0,0 +1,75 @@
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// Reads the clipboard as text, using whichever of the usual
+/// command line clipboard tools is installed.
+pub fn read_text() -> io::Result<String> {
+    let commands: [(&str, &[&str]); 3] = [
+        ("wl-paste", &["--no-newline"]),
+        ("xclip", &["-selection", "clipboard", "-out"]),
+        ("xsel", &["--clipboard", "--output"]),
+    ];
+
+    for (program, args) in commands {
+        let output = match Command::new(program).args(args).output() {
+            Ok(output) => output,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+
+        // An empty clipboard makes some of them fail
+        if !output.status.success() {
+            return Ok(String::new());
+        }
+        return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        "no clipboard tool found, install wl-clipboard, xclip or xsel",
+    ))
+}
+
+/// Puts a PNG image on the clipboard, using whichever of the
+/// usual command line clipboard tools is installed.
+pub fn write_png(bytes: &[u8]) -> io::Result<()> {
+    let commands: [(&str, &[&str]); 2] = [
+        ("wl-copy", &["--type", "image/png"]),
+        (
+            "xclip",
+            &["-selection", "clipboard", "-target", "image/png", "-in"],
+        ),
+    ];
+
+    for (program, args) in commands {
+        // Both fork a copy that stays around in the background to
+        // serve the image, which would hold piped output open, so
+        // only the exit status of the first process is waited on
+        let mut child = match Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(bytes)?;
+        }
+        let status = child.wait()?;
+        return if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!("{program} exited with {status}")))
+        };
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        "no clipboard tool found, install wl-clipboard or xclip",
+    ))
+}
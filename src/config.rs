@@ -0,0 +1,67 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+/// How many recently used projects are remembered.
+const MAX_RECENT: usize = 8;
+
+/// Preferences that are kept between sessions.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub recent: Vec<PathBuf>,
+    /// The color new and reset canvases are filled with.
+    pub fill_color: Option<[u8; 3]>,
+    /// The tool that was last used, e.g. "square".
+    pub brush: Option<String>,
+    pub brush_size: Option<usize>,
+    /// Whether the interface uses larger, high contrast controls.
+    pub high_contrast: bool,
+}
+
+/// Returns the folder the config and presets are kept in,
+/// following the XDG base directory spec when possible.
+pub fn dir() -> PathBuf {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_default()
+        .join("ap-drawing")
+}
+
+impl Config {
+    /// Returns where the config is stored.
+    fn path() -> PathBuf {
+        dir().join("config.json")
+    }
+
+    /// Loads the saved config, falling back to the defaults if
+    /// there is none or it can't be read.
+    pub fn load() -> Self {
+        let mut config: Config = fs::read_to_string(Self::path())
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default();
+        config.recent.retain(|path| path.exists());
+        config
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = Self::path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    /// Moves `path` to the front of the recent projects.
+    pub fn add_recent(&mut self, path: PathBuf) {
+        let path = path.canonicalize().unwrap_or(path);
+        self.recent.retain(|recent| *recent != path);
+        self.recent.insert(0, path);
+        self.recent.truncate(MAX_RECENT);
+    }
+}
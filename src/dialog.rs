@@ -0,0 +1,58 @@
+use std::io;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+/// A file picker open on another thread so the
+/// app keeps drawing while it is up.
+pub struct Dialog {
+    receiver: Receiver<io::Result<Option<PathBuf>>>,
+}
+
+impl Dialog {
+    /// Opens the desktop's file picker, trying zenity and then kdialog.
+    pub fn pick_file() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = sender.send(run());
+        });
+        Dialog { receiver }
+    }
+
+    /// Returns the picked file once the dialog has closed. The inner
+    /// `None` means the dialog was cancelled.
+    pub fn result(&self) -> Option<io::Result<Option<PathBuf>>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                Some(Err(io::Error::other("file dialog stopped unexpectedly")))
+            }
+        }
+    }
+}
+
+fn run() -> io::Result<Option<PathBuf>> {
+    let commands: [(&str, &[&str]); 2] = [
+        ("zenity", &["--file-selection"]),
+        ("kdialog", &["--getopenfilename", "."]),
+    ];
+
+    for (program, args) in commands {
+        let output = match Command::new(program).args(args).output() {
+            Ok(output) => output,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+
+        // Both exit unsuccessfully when the user cancels
+        let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        return Ok((output.status.success() && !path.is_empty()).then(|| PathBuf::from(path)));
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        "no file dialog found, install zenity or kdialog",
+    ))
+}
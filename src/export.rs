@@ -0,0 +1,240 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use nannou::image::{
+    self, imageops, DynamicImage, ImageOutputFormat, ImageResult, RgbImage, RgbaImage,
+};
+use nannou::prelude::*;
+
+use crate::canvas::{image_row, Pixel};
+use crate::palette::{luminance, to_hex};
+
+/// Characters from darkest to brightest for ASCII exports.
+const ASCII_RAMP: &[u8] = b" .:-=+*#%@";
+
+/// Extra options for PNG exports.
+#[derive(Clone, Copy, Default)]
+pub struct PngOptions {
+    /// How many pixels of border to add around each side.
+    pub padding: u32,
+    /// The color of the border and of the gaps between
+    /// cells, or `None` for transparent.
+    pub padding_color: Option<Rgb8>,
+    /// How many pixels wide each cell is drawn.
+    pub scale: u32,
+    /// How much of each cell is left as a gap when `scale` is
+    /// above 1, as a fraction of its width.
+    pub cell_gap: f32,
+    /// Softens hard edges with a light blur, for when a
+    /// smoothed asset is wanted instead of crisp pixels.
+    pub smooth: bool,
+    /// How many cells from the left and top of a larger tile the
+    /// canvas sits, filled in like the padding.
+    pub offset: (u32, u32),
+}
+
+/// A texture that can be exported alongside the canvas for game
+/// engines that expect paired maps.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Companion {
+    /// A flat normal map with every cell facing the viewer.
+    Normal,
+    /// A grayscale depth map taken from each cell's brightness.
+    Depth,
+}
+
+impl Companion {
+    /// What is added to the export's file name, e.g. `sprite_normal.png`.
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            Companion::Normal => "normal",
+            Companion::Depth => "depth",
+        }
+    }
+
+    /// Returns the map's cells for the given canvas.
+    pub fn render(&self, pixels: &[Vec<Pixel>]) -> Vec<Vec<Pixel>> {
+        pixels
+            .iter()
+            .map(|column| {
+                column
+                    .iter()
+                    .map(|pixel| {
+                        let color = match self {
+                            Companion::Normal => rgb8(128, 128, 255),
+                            Companion::Depth => {
+                                let depth = luminance(pixel.color);
+                                rgb8(depth, depth, depth)
+                            }
+                        };
+                        Pixel {
+                            color,
+                            ..pixel.clone()
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Writes the canvas to `path` as an SVG with one rect per run
+/// of same colored cells in a row. Cells matching `background`
+/// are left out so they show up as transparent.
+pub fn write_svg(path: &Path, pixels: &[Vec<Pixel>], background: Option<Rgb8>) -> io::Result<()> {
+    let width = pixels.len();
+    let height = pixels.first().map_or(0, |column| column.len());
+    let mut out = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+         viewBox=\"0 0 {width} {height}\" shape-rendering=\"crispEdges\">\n"
+    );
+
+    // The grid's y axis points up while SVG's points down
+    for row in 0..height {
+        let y = image_row(row, height);
+        let mut x = 0;
+        while x < width {
+            let color = pixels[x][y].color;
+            let mut run = 1;
+            while x + run < width && pixels[x + run][y].color == color {
+                run += 1;
+            }
+
+            if Some(color) != background {
+                out.push_str(&format!(
+                    "  <rect x=\"{x}\" y=\"{row}\" width=\"{run}\" height=\"1\" \
+                     fill=\"#{}\"/>\n",
+                    to_hex(color)
+                ));
+            }
+            x += run;
+        }
+    }
+
+    out.push_str("</svg>\n");
+    fs::write(path, out)
+}
+
+/// Writes the canvas to `path` as text with one character per cell,
+/// picked by how bright the cell is. With `ansi`, each character is
+/// also given the cell's color using terminal escape codes.
+pub fn write_ascii(path: &Path, pixels: &[Vec<Pixel>], ansi: bool) -> io::Result<()> {
+    let height = pixels.first().map_or(0, |column| column.len());
+    let mut out = String::new();
+
+    // The grid's y axis points up while text runs down
+    for y in (0..height).rev() {
+        for column in pixels {
+            let color = column[y].color;
+            let index = luminance(color) as usize * (ASCII_RAMP.len() - 1) / 255;
+            if ansi {
+                out.push_str(&format!(
+                    "\x1b[38;2;{};{};{}m",
+                    color.red, color.green, color.blue
+                ));
+            }
+            out.push(ASCII_RAMP[index] as char);
+        }
+        if ansi {
+            out.push_str("\x1b[0m");
+        }
+        out.push('\n');
+    }
+
+    fs::write(path, out)
+}
+
+/// Renders the canvas to an image with one pixel per cell.
+pub fn render(pixels: &[Vec<Pixel>]) -> RgbImage {
+    let width = pixels.len() as u32;
+    let height = pixels.first().map_or(0, |column| column.len()) as u32;
+
+    // The grid's y axis points up while the image's points down
+    RgbImage::from_fn(width, height, |x, y| {
+        let color = pixels[x as usize][image_row(y as usize, height as usize)].color;
+        image::Rgb([color.red, color.green, color.blue])
+    })
+}
+
+/// Blurs an image with a 1-2-1 kernel, first across and then down.
+/// Pixels past the edges count as copies of the edge pixels.
+fn smooth(image: &RgbImage) -> RgbImage {
+    let (width, height) = image.dimensions();
+    let blur = |image: &RgbImage, dx: i64, dy: i64| {
+        RgbImage::from_fn(width, height, |x, y| {
+            let at = |offset: i64| {
+                let x = (x as i64 + dx * offset).clamp(0, width as i64 - 1) as u32;
+                let y = (y as i64 + dy * offset).clamp(0, height as i64 - 1) as u32;
+                image.get_pixel(x, y).0
+            };
+            let (before, here, after) = (at(-1), at(0), at(1));
+            image::Rgb(std::array::from_fn(|i| {
+                ((before[i] as u16 + here[i] as u16 * 2 + after[i] as u16 + 2) / 4) as u8
+            }))
+        })
+    };
+    blur(&blur(image, 1, 0), 0, 1)
+}
+
+/// Writes the canvas to `path` as a PNG.
+pub fn write_png(path: &Path, pixels: &[Vec<Pixel>], options: &PngOptions) -> ImageResult<()> {
+    render_png(pixels, options).save(path)
+}
+
+/// Encodes the canvas as the bytes of a PNG file.
+pub fn encode_png(pixels: &[Vec<Pixel>], options: &PngOptions) -> ImageResult<Vec<u8>> {
+    let mut bytes = Vec::new();
+    render_png(pixels, options).write_to(&mut bytes, ImageOutputFormat::Png)?;
+    Ok(bytes)
+}
+
+/// Renders the canvas with all of the PNG options applied.
+fn render_png(pixels: &[Vec<Pixel>], options: &PngOptions) -> DynamicImage {
+    let mut image = render(pixels);
+    if options.smooth {
+        image = smooth(&image);
+    }
+    if options.padding == 0 && options.scale <= 1 && options.offset == (0, 0) {
+        return DynamicImage::ImageRgb8(image);
+    }
+
+    let border = match options.padding_color {
+        Some(color) => image::Rgba([color.red, color.green, color.blue, 255]),
+        None => image::Rgba([0, 0, 0, 0]),
+    };
+    let image = if options.scale > 1 {
+        spread(&image, options.scale, options.cell_gap, border)
+    } else {
+        DynamicImage::ImageRgb8(image).into_rgba8()
+    };
+    let padding = options.padding;
+    let scale = options.scale.max(1);
+    let (left, top) = (options.offset.0 * scale, options.offset.1 * scale);
+    let mut padded = RgbaImage::from_pixel(
+        image.width() + padding * 2 + left,
+        image.height() + padding * 2 + top,
+        border,
+    );
+    imageops::replace(&mut padded, &image, padding + left, padding + top);
+    DynamicImage::ImageRgba8(padded)
+}
+
+/// Scales each pixel up to a `scale` by `scale` block, leaving `gap`
+/// of the block's width around it filled with `border`.
+fn spread(image: &RgbImage, scale: u32, gap: f32, border: image::Rgba<u8>) -> RgbaImage {
+    // Split the gap across both sides, always leaving some of the cell
+    let gap = ((scale as f32 * gap).round() as u32).min(scale - 1);
+    let (before, after) = (gap / 2, gap - gap / 2);
+    let inside = |offset: u32| offset >= before && offset < scale - after;
+
+    let (width, height) = image.dimensions();
+    RgbaImage::from_fn(width * scale, height * scale, |x, y| {
+        if inside(x % scale) && inside(y % scale) {
+            let [red, green, blue] = image.get_pixel(x / scale, y / scale).0;
+            image::Rgba([red, green, blue, 255])
+        } else {
+            border
+        }
+    })
+}
@@ -0,0 +1,287 @@
+use nannou::prelude::*;
+
+use crate::canvas::Pixel;
+use crate::layer::Layer;
+
+/// How many bytes of operations are remembered by default
+/// before the oldest are forgotten.
+pub const DEFAULT_BUDGET: usize = 16 * 1024 * 1024;
+
+/// A single cell that an operation changed.
+struct Change {
+    x: usize,
+    y: usize,
+    before: Rgb8,
+    after: Rgb8,
+}
+
+/// A layer that an operation merged away. It is kept here while
+/// the operation is applied and lives in the stack again, directly
+/// above the layer owning this history, once it is undone.
+struct Merged {
+    layer: Option<Box<Layer>>,
+    /// How many cells the layer has, so its memory is counted
+    /// the same whether or not it is kept here.
+    cells: usize,
+}
+
+/// The cells changed by one operation, along with a label
+/// naming the operation, e.g. "Draw" or "Fill".
+pub struct Entry {
+    pub label: &'static str,
+    changes: Vec<Change>,
+    merged: Option<Merged>,
+}
+
+impl Entry {
+    /// Roughly how much memory the entry takes up.
+    fn bytes(&self) -> usize {
+        let merged = self
+            .merged
+            .as_ref()
+            .map_or(0, |merged| merged.cells * std::mem::size_of::<Pixel>());
+        std::mem::size_of::<Entry>() + self.changes.len() * std::mem::size_of::<Change>() + merged
+    }
+}
+
+/// The undo and redo stacks of a canvas. Operations are
+/// recorded by taking a snapshot before they start and
+/// comparing it with the canvas once they are done.
+pub struct History {
+    undo: Vec<Entry>,
+    redo: Vec<Entry>,
+    snapshot: Option<(&'static str, Vec<Vec<Rgb8>>)>,
+    /// Whether the canvas changed since it was last saved.
+    unsaved: bool,
+    /// Roughly how many bytes the undo and redo stacks take up.
+    bytes: usize,
+    /// How many bytes the stacks may take up before the
+    /// oldest operations are forgotten.
+    budget: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            snapshot: None,
+            unsaved: false,
+            bytes: 0,
+            budget: DEFAULT_BUDGET,
+        }
+    }
+}
+
+impl History {
+    /// Remembers the canvas before an operation. Does nothing
+    /// if an operation is already in progress.
+    pub fn begin(&mut self, label: &'static str, pixels: &[Vec<Pixel>]) {
+        if self.snapshot.is_none() {
+            let colors = pixels
+                .iter()
+                .map(|row| row.iter().map(|pixel| pixel.color).collect())
+                .collect();
+            self.snapshot = Some((label, colors));
+        }
+    }
+
+    /// Finishes the operation started with `begin`, recording
+    /// every cell that changed since. Nothing is recorded if
+    /// the canvas is unchanged.
+    pub fn commit(&mut self, pixels: &[Vec<Pixel>]) {
+        if let Some((label, changes)) = self.changes(pixels) {
+            if !changes.is_empty() {
+                self.push(Entry {
+                    label,
+                    changes,
+                    merged: None,
+                });
+            }
+        }
+    }
+
+    /// Finishes the operation started with `begin` like `commit`,
+    /// also keeping `layer`, which the operation took off the
+    /// stack from directly above this history's layer. It is
+    /// recorded even if no cells changed, so the layer can always
+    /// be put back.
+    pub fn commit_merge(&mut self, pixels: &[Vec<Pixel>], layer: Layer) {
+        if let Some((label, changes)) = self.changes(pixels) {
+            let cells = layer.pixels.iter().map(Vec::len).sum();
+            self.push(Entry {
+                label,
+                changes,
+                merged: Some(Merged {
+                    layer: Some(Box::new(layer)),
+                    cells,
+                }),
+            });
+        }
+    }
+
+    /// Ends the operation in progress, returning its label and
+    /// every cell that changed since it began.
+    fn changes(&mut self, pixels: &[Vec<Pixel>]) -> Option<(&'static str, Vec<Change>)> {
+        let (label, before) = self.snapshot.take()?;
+
+        let mut changes = Vec::new();
+        for (x, row) in pixels.iter().enumerate() {
+            for (y, pixel) in row.iter().enumerate() {
+                if before[x][y] != pixel.color {
+                    changes.push(Change {
+                        x,
+                        y,
+                        before: before[x][y],
+                        after: pixel.color,
+                    });
+                }
+            }
+        }
+
+        Some((label, changes))
+    }
+
+    /// Adds a finished operation, forgetting anything that could
+    /// have been redone.
+    fn push(&mut self, entry: Entry) {
+        self.unsaved = true;
+        self.bytes -= self
+            .redo
+            .drain(..)
+            .map(|entry| entry.bytes())
+            .sum::<usize>();
+        self.bytes += entry.bytes();
+        self.undo.push(entry);
+        self.evict();
+    }
+
+    /// Sets how many bytes of operations are remembered, forgetting
+    /// the oldest ones straight away if there are already too many.
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.evict();
+    }
+
+    /// Roughly how many bytes the remembered operations take up.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Forgets the oldest operations until the rest fit in the
+    /// budget. The newest is always kept so it can be undone.
+    fn evict(&mut self) {
+        while self.bytes > self.budget && self.undo.len() > 1 {
+            let entry = self.undo.remove(0);
+            self.bytes -= entry.bytes();
+        }
+    }
+
+    /// Records an operation that has already been applied, by
+    /// comparing the canvas with how it looked beforehand.
+    pub fn record(&mut self, label: &'static str, before: &[Vec<Pixel>], after: &[Vec<Pixel>]) {
+        self.snapshot = None;
+        self.begin(label, before);
+        self.commit(after);
+    }
+
+    /// Forgets everything, e.g. when the canvas is resized
+    /// and the recorded cells no longer line up.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.bytes = 0;
+        self.snapshot = None;
+        self.unsaved = true;
+    }
+
+    /// Reverts the last operation, returning its label. A layer it
+    /// merged away is put back into `layers` at `above`.
+    pub fn undo(
+        &mut self,
+        pixels: &mut [Vec<Pixel>],
+        layers: &mut Vec<Layer>,
+        above: usize,
+    ) -> Option<&'static str> {
+        let mut entry = self.undo.pop()?;
+        self.unsaved = true;
+        for change in &entry.changes {
+            pixels[change.x][change.y].color = change.before;
+        }
+        if let Some(layer) = entry.merged.as_mut().and_then(|merged| merged.layer.take()) {
+            layers.insert(above, *layer);
+        }
+
+        let label = entry.label;
+        self.redo.push(entry);
+        Some(label)
+    }
+
+    /// Reapplies the last undone operation, returning its label. A
+    /// layer it merged away is taken out of `layers` at `above` again.
+    pub fn redo(
+        &mut self,
+        pixels: &mut [Vec<Pixel>],
+        layers: &mut Vec<Layer>,
+        above: usize,
+    ) -> Option<&'static str> {
+        let mut entry = self.redo.pop()?;
+        self.unsaved = true;
+        for change in &entry.changes {
+            pixels[change.x][change.y].color = change.after;
+        }
+        if let Some(merged) = &mut entry.merged {
+            merged.layer = Some(Box::new(layers.remove(above)));
+        }
+
+        let label = entry.label;
+        self.undo.push(entry);
+        Some(label)
+    }
+
+    /// The labels of every remembered operation, oldest first.
+    pub fn labels(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.undo
+            .iter()
+            .chain(self.redo.iter().rev())
+            .map(|entry| entry.label)
+    }
+
+    /// How many of the remembered operations are applied.
+    pub fn position(&self) -> usize {
+        self.undo.len()
+    }
+
+    /// Undoes or redoes operations until exactly `position`
+    /// of them are applied.
+    pub fn jump(
+        &mut self,
+        pixels: &mut [Vec<Pixel>],
+        layers: &mut Vec<Layer>,
+        above: usize,
+        position: usize,
+    ) {
+        while self.undo.len() > position && self.undo(pixels, layers, above).is_some() {}
+        while self.undo.len() < position && self.redo(pixels, layers, above).is_some() {}
+    }
+
+    /// Whether anything was changed, undone or redone since
+    /// `mark_saved` was last called.
+    pub fn is_unsaved(&self) -> bool {
+        self.unsaved
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.unsaved = false;
+    }
+
+    /// The label of the operation `undo` would revert.
+    pub fn next_undo(&self) -> Option<&'static str> {
+        self.undo.last().map(|entry| entry.label)
+    }
+
+    /// The label of the operation `redo` would reapply.
+    pub fn next_redo(&self) -> Option<&'static str> {
+        self.redo.last().map(|entry| entry.label)
+    }
+}
@@ -0,0 +1,133 @@
+use std::path::Path;
+
+use nannou::image::{self, ImageResult, RgbImage};
+use nannou::prelude::*;
+
+use crate::canvas::image_row;
+use crate::canvas::Pixel;
+
+/// How an image is shrunk or stretched to fit the grid.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Resample {
+    /// Takes the source pixel nearest each cell's center,
+    /// which keeps pixel art crisp.
+    Nearest,
+    /// Averages every source pixel a cell covers, which keeps
+    /// more detail when shrinking photos.
+    Area,
+}
+
+/// How an image that isn't the grid's shape is made to cover it.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Aspect {
+    /// Squashes the whole image onto the grid.
+    Stretch,
+    /// Fits the whole image inside the grid, leaving
+    /// the rest of the cells as the background.
+    Fit,
+    /// Covers the whole grid, cropping whatever sticks out.
+    Fill,
+}
+
+/// Loads the image at `path` and resamples it to the grid.
+pub fn load_image(
+    path: &Path,
+    columns: usize,
+    rows: usize,
+    method: Resample,
+    aspect: Aspect,
+    background: Rgb8,
+) -> ImageResult<Vec<Vec<Pixel>>> {
+    let image = image::open(path)?.to_rgb8();
+    Ok(resample(&image, columns, rows, method, aspect, background))
+}
+
+/// Resamples an image to a grid `columns` cells across and `rows`
+/// cells tall. Cells that the image doesn't reach are set to
+/// `background`.
+pub fn resample(
+    image: &RgbImage,
+    columns: usize,
+    rows: usize,
+    method: Resample,
+    aspect: Aspect,
+    background: Rgb8,
+) -> Vec<Vec<Pixel>> {
+    let (width, height) = image.dimensions();
+    let mut pixels = vec![vec![Pixel::default(); rows]; columns];
+
+    // The part of the image the grid covers, which may reach past its
+    // edges, with the same shape as the grid unless stretching
+    let ratio = columns as f32 / rows as f32;
+    let (region_width, region_height) = match aspect {
+        Aspect::Stretch => (width as f32, height as f32),
+        Aspect::Fit => {
+            let region_width = (width as f32).max(height as f32 * ratio);
+            (region_width, region_width / ratio)
+        }
+        Aspect::Fill => {
+            let region_width = (width as f32).min(height as f32 * ratio);
+            (region_width, region_width / ratio)
+        }
+    };
+    let left = (width as f32 - region_width) / 2.0;
+    let top = (height as f32 - region_height) / 2.0;
+
+    // Where a cell boundary along an axis of `cells` cells lands in the image
+    let source =
+        |i: f32, cells: usize, offset: f32, extent: f32| offset + i / cells as f32 * extent;
+
+    // The source pixels a cell covers along one axis, at least one wide
+    let span = |i: usize, cells: usize, offset: f32, extent: f32, size: u32| {
+        let start = source(i as f32, cells, offset, extent).max(0.0) as u32;
+        let end = source((i + 1) as f32, cells, offset, extent)
+            .ceil()
+            .max(0.0) as u32;
+        start.min(size - 1)..end.clamp(start + 1, size)
+    };
+
+    for (x, column) in pixels.iter_mut().enumerate() {
+        for (y, pixel) in column.iter_mut().enumerate() {
+            // The grid's y axis points up while the image's points down
+            let row = image_row(y, rows);
+            let src_x = source(x as f32 + 0.5, columns, left, region_width);
+            let src_y = source(row as f32 + 0.5, rows, top, region_height);
+            if src_x < 0.0 || src_y < 0.0 || src_x >= width as f32 || src_y >= height as f32 {
+                pixel.color = background;
+                continue;
+            }
+
+            pixel.color = match method {
+                Resample::Nearest => {
+                    let [r, g, b] = image
+                        .get_pixel(
+                            (src_x as u32).min(width - 1),
+                            (src_y as u32).min(height - 1),
+                        )
+                        .0;
+                    rgb8(r, g, b)
+                }
+                Resample::Area => {
+                    let mut sum = [0u32; 3];
+                    let mut count = 0;
+                    for src_x in span(x, columns, left, region_width, width) {
+                        for src_y in span(row, rows, top, region_height, height) {
+                            let channels = image.get_pixel(src_x, src_y).0;
+                            for (sum, channel) in sum.iter_mut().zip(channels) {
+                                *sum += channel as u32;
+                            }
+                            count += 1;
+                        }
+                    }
+                    rgb8(
+                        ((sum[0] + count / 2) / count) as u8,
+                        ((sum[1] + count / 2) / count) as u8,
+                        ((sum[2] + count / 2) / count) as u8,
+                    )
+                }
+            };
+        }
+    }
+
+    pixels
+}
@@ -0,0 +1,98 @@
+use nannou::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::canvas::Pixel;
+use crate::{history, palette};
+
+/// How a layer's colors combine with the layers below it.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Blend {
+    Normal,
+    Multiply,
+    Additive,
+    Screen,
+}
+
+impl Blend {
+    /// Combines a color with the one beneath it on each channel.
+    pub fn apply(&self, below: Rgb8, above: Rgb8) -> Rgb8 {
+        let channel = |below: u8, above: u8| match self {
+            Blend::Normal => above,
+            Blend::Multiply => (below as u16 * above as u16 / 255) as u8,
+            Blend::Additive => below.saturating_add(above),
+            Blend::Screen => 255 - ((255 - below) as u16 * (255 - above) as u16 / 255) as u8,
+        };
+        rgb8(
+            channel(below.red, above.red),
+            channel(below.green, above.green),
+            channel(below.blue, above.blue),
+        )
+    }
+}
+
+/// One layer of a canvas. The active layer's cells and history
+/// are lent out to the canvas while it is being drawn on, so they
+/// are only stored here for the other layers.
+pub struct Layer {
+    pub name: String,
+    pub pixels: Vec<Vec<Pixel>>,
+    pub history: history::History,
+    pub visible: bool,
+    pub blend: Blend,
+    /// How strongly the layer covers the ones below, from 0.0 to 1.0.
+    pub opacity: f32,
+}
+
+impl Layer {
+    /// Creates a layer filled with `fill`, which should be the
+    /// color `composite` treats as transparent so the layers below
+    /// show through.
+    pub fn new(name: String, width: usize, height: usize, fill: Rgb8) -> Self {
+        let pixel = Pixel {
+            color: fill,
+            ..Default::default()
+        };
+        Layer {
+            name,
+            pixels: vec![vec![pixel; height]; width],
+            history: history::History::default(),
+            visible: true,
+            blend: Blend::Normal,
+            opacity: 1.0,
+        }
+    }
+}
+
+/// Stacks the layers from bottom to top, blending each over the
+/// ones below at its opacity. Cells of the `transparent` color let
+/// the layers below show through, and hidden layers are skipped.
+pub fn composite<'a, I>(layers: I, width: usize, height: usize, transparent: Rgb8) -> Vec<Vec<Rgb8>>
+where
+    I: IntoIterator<Item = (&'a [Vec<Pixel>], &'a Layer)>,
+{
+    let mut out = vec![vec![transparent; height]; width];
+
+    for (pixels, layer) in layers {
+        if !layer.visible {
+            continue;
+        }
+
+        for (column, cells) in out.iter_mut().zip(pixels) {
+            for (cell, pixel) in column.iter_mut().zip(cells) {
+                if pixel.color == transparent {
+                    continue;
+                }
+
+                // There is nothing to blend with over empty cells
+                let color = if *cell == transparent {
+                    pixel.color
+                } else {
+                    layer.blend.apply(*cell, pixel.color)
+                };
+                *cell = palette::lerp(*cell, color, layer.opacity);
+            }
+        }
+    }
+
+    out
+}
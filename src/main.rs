@@ -1,8 +1,32 @@
+mod adjust;
+mod batch;
+mod canvas;
+mod clipboard;
+mod config;
+mod dialog;
+mod export;
+mod history;
+mod import;
+mod layer;
+mod palette;
+mod preset;
+mod project;
+mod selection;
+mod transform;
+mod tween;
+
 use core::f32;
 use std::marker::PhantomData;
-use std::usize;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
 
+use batch::Batch;
+use canvas::{calc_line_pixels, Canvas, Pixel};
+use config::Config;
+use layer::{Blend, Layer};
 use nannou::prelude::*;
+use nannou::rand::rngs::StdRng;
+use nannou::rand::{Rng, SeedableRng};
 use nannou::{
     event::{Update, WindowEvent},
     App, Frame,
@@ -10,6 +34,7 @@ use nannou::{
 use nannou_egui::egui::epaint::Shadow;
 use nannou_egui::egui::{Vec2, Visuals};
 use nannou_egui::{self, egui, Egui};
+use palette::PaletteFormat;
 
 const OVERLAY: Rgba8 = Rgba8 {
     color: Rgb {
@@ -21,57 +46,815 @@ const OVERLAY: Rgba8 = Rgba8 {
     alpha: 100,
 };
 
+const SELECTION: Rgb8 = Rgb8 {
+    red: 0,
+    green: 170,
+    blue: 255,
+    standard: PhantomData,
+};
+
+const ANCHOR: Rgb8 = Rgb8 {
+    red: 255,
+    green: 220,
+    blue: 0,
+    standard: PhantomData,
+};
+
+const RULER: Rgba8 = Rgba8 {
+    color: Rgb {
+        red: 0,
+        green: 0,
+        blue: 0,
+        standard: PhantomData,
+    },
+    alpha: 160,
+};
+
+const OUTLINE: Rgb8 = Rgb8 {
+    red: 255,
+    green: 0,
+    blue: 0,
+    standard: PhantomData,
+};
+
+const ORPHAN: Rgb8 = Rgb8 {
+    red: 255,
+    green: 120,
+    blue: 0,
+    standard: PhantomData,
+};
+
+const GUIDE: Rgba8 = Rgba8 {
+    color: Rgb {
+        red: 255,
+        green: 0,
+        blue: 255,
+        standard: PhantomData,
+    },
+    alpha: 90,
+};
+
+/// The keys that pick the first nine swatches, in order.
+const SWATCH_KEYS: [Key; 9] = [
+    Key::Key1,
+    Key::Key2,
+    Key::Key3,
+    Key::Key4,
+    Key::Key5,
+    Key::Key6,
+    Key::Key7,
+    Key::Key8,
+    Key::Key9,
+];
+
+/// The thickness of the rulers in points.
+const RULER_SIZE: f32 = 18.0;
+
+/// How often the region a fill would cover is worked out again
+/// while hovering, since flooding large grids is slow.
+const FILL_PREVIEW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long a delayed brush preview takes to fade in.
+const PREVIEW_FADE: Duration = Duration::from_millis(150);
+
+/// How often the color usage window counts the canvas's colors again.
+const COLOR_COUNT_INTERVAL: Duration = Duration::from_millis(500);
+
 enum Brush {
     Circle,
     Square,
+    Fill,
+    Line,
+    Lasso,
+    Wand,
+    Eyedropper,
 }
 
-#[derive(Clone)]
-struct Pixel {
-    color: Rgb8,
-    x: f32,
-    y: f32,
+impl Brush {
+    const ALL: [Brush; 7] = [
+        Brush::Circle,
+        Brush::Square,
+        Brush::Fill,
+        Brush::Line,
+        Brush::Lasso,
+        Brush::Wand,
+        Brush::Eyedropper,
+    ];
+
+    /// The name the brush is saved under in the config.
+    fn tag(&self) -> &'static str {
+        match self {
+            Brush::Circle => "circle",
+            Brush::Square => "square",
+            Brush::Fill => "fill",
+            Brush::Line => "line",
+            Brush::Lasso => "lasso",
+            Brush::Wand => "wand",
+            Brush::Eyedropper => "eyedropper",
+        }
+    }
+
+    /// Looks a brush up by its tag, falling back to the square
+    /// brush for tags that are unknown or no longer exist.
+    fn from_tag(tag: &str) -> Self {
+        Brush::ALL
+            .into_iter()
+            .find(|brush| brush.tag() == tag)
+            .unwrap_or(Brush::Square)
+    }
+
+    /// Whether the brush paints on every frame the mouse
+    /// is held, rather than once per click.
+    fn is_continuous(&self) -> bool {
+        match self {
+            Brush::Circle | Brush::Square => true,
+            Brush::Fill | Brush::Line | Brush::Lasso | Brush::Wand | Brush::Eyedropper => false,
+        }
+    }
 }
 
-impl Default for Pixel {
-    fn default() -> Self {
-        Pixel {
-            color: BLACK,
-            x: 0.0,
-            y: 0.0,
+#[derive(Clone, Copy, PartialEq)]
+enum FrameCap {
+    Thirty,
+    Sixty,
+    Uncapped,
+}
+
+impl FrameCap {
+    /// The minimum time between updates, if any.
+    fn interval(&self) -> Option<Duration> {
+        match self {
+            FrameCap::Thirty => Some(Duration::from_secs_f64(1.0 / 30.0)),
+            FrameCap::Sixty => Some(Duration::from_secs_f64(1.0 / 60.0)),
+            FrameCap::Uncapped => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ResetKey {
+    Immediate,
+    Ctrl,
+    Confirm,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum MiddleClick {
+    Paint,
+    Pan,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Corner {
+    /// The egui anchor that pins a window to this corner.
+    fn align(&self) -> egui::Align2 {
+        match self {
+            Corner::TopLeft => egui::Align2::LEFT_TOP,
+            Corner::TopRight => egui::Align2::RIGHT_TOP,
+            Corner::BottomLeft => egui::Align2::LEFT_BOTTOM,
+            Corner::BottomRight => egui::Align2::RIGHT_BOTTOM,
+        }
+    }
+}
+
+/// What is shown behind cells of the background color.
+#[derive(Clone, Copy, PartialEq)]
+enum Backdrop {
+    Solid,
+    Checker,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Origin {
+    Center,
+    TopLeft,
+}
+
+impl Origin {
+    /// Converts a grid index, which counts from the bottom left,
+    /// to the coordinates shown to the user.
+    fn coordinates(&self, x: i32, y: i32, width: usize, height: usize) -> (i32, i32) {
+        let (width, height) = (width as i32, height as i32);
+        match self {
+            Origin::Center => (x - width / 2, y - height / 2),
+            // The same flip as `canvas::image_row`, but for
+            // cells that may lie outside of the grid
+            Origin::TopLeft => (x, height - 1 - y),
+        }
+    }
+}
+
+/// A single open canvas.
+struct Document {
+    /// How many cells across and tall the canvas will be, which the
+    /// settings change before the canvas is reset to match.
+    width: usize,
+    height: usize,
+    canvas: Canvas,
+    selection: Option<selection::Mask>,
+    file_name: String,
+    history: history::History,
+    /// How far the view is zoomed in, where 1.0 fits the window.
+    zoom: f32,
+    /// How far the canvas is moved from the window's center, in cells.
+    pan: Point2,
+    /// The layers from the bottom up. `canvas` and `history`
+    /// belong to the active one while it is being drawn on.
+    layers: Vec<Layer>,
+    active_layer: usize,
+    /// Whether anything the histories don't record, such as the
+    /// layers or their settings, changed since the canvas was saved.
+    dirty: bool,
+}
+
+impl Document {
+    fn new(width: usize, height: usize, file_name: String, fill: Rgb8) -> Self {
+        Document {
+            width,
+            height,
+            canvas: Canvas::new(width, height, fill),
+            selection: None,
+            file_name,
+            history: history::History::default(),
+            zoom: 1.0,
+            pan: Point2::ZERO,
+            layers: vec![Layer::new(String::from("Layer 1"), 0, 0, fill)],
+            active_layer: 0,
+            dirty: false,
+        }
+    }
+
+    /// Where to write the canvas, with `suffix` such as ".png" added
+    /// to the file name. The suffix is appended rather than set as
+    /// the extension so dots already in the name are kept.
+    fn path(&self, suffix: &str) -> PathBuf {
+        PathBuf::from(format!("{}{suffix}", self.file_name))
+    }
+
+    /// How many cells long the canvas's longest side is.
+    fn longest_side(&self) -> usize {
+        self.width.max(self.height)
+    }
+
+    /// Every layer's cells from the bottom up, along with the layer.
+    fn layer_stack(&self) -> impl Iterator<Item = (&[Vec<Pixel>], &Layer)> {
+        self.layers.iter().enumerate().map(|(i, layer)| {
+            let pixels = if i == self.active_layer {
+                &self.canvas.pixels
+            } else {
+                &layer.pixels
+            };
+            (pixels.as_slice(), layer)
+        })
+    }
+
+    /// The visible layers flattened into a single grid, laid
+    /// out in the same place as the active layer's cells.
+    fn flatten(&self, transparent: Rgb8) -> Vec<Vec<Pixel>> {
+        let colors = layer::composite(self.layer_stack(), self.width, self.height, transparent);
+        self.canvas
+            .pixels
+            .iter()
+            .zip(colors)
+            .map(|(column, colors)| {
+                column
+                    .iter()
+                    .zip(colors)
+                    .map(|(pixel, color)| Pixel {
+                        color,
+                        x: pixel.x,
+                        y: pixel.y,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Whether any layer changed since the canvas was last saved.
+    fn is_unsaved(&self) -> bool {
+        self.dirty
+            || self.history.is_unsaved()
+            || self.layers.iter().any(|layer| layer.history.is_unsaved())
+    }
+
+    fn mark_saved(&mut self) {
+        self.dirty = false;
+        self.history.mark_saved();
+        for layer in &mut self.layers {
+            layer.history.mark_saved();
+        }
+    }
+
+    /// Turns every layer counterclockwise about the center of the
+    /// canvas, first growing the grid to fit the corners if `grow`
+    /// is set. Resizing forgets the history, as pixels no longer
+    /// line up with the recorded cells.
+    fn rotate(&mut self, degrees: f32, grow: bool, background: Rgb8) {
+        // 64 cells is the largest grid the settings allow
+        let (width, height) = if grow {
+            let (width, height) = transform::rotated_size(self.width, self.height, degrees);
+            (width.clamp(self.width, 64), height.clamp(self.height, 64))
+        } else {
+            (self.width, self.height)
+        };
+        let resized = (width, height) != (self.width, self.height);
+
+        let rotated = transform::rotate(&self.canvas.pixels, degrees, width, height, background);
+        if resized {
+            self.history.clear();
+        } else {
+            self.history.record("Rotate", &self.canvas.pixels, &rotated);
+        }
+        self.canvas.pixels = rotated;
+
+        let active = self.active_layer;
+        for (i, layer) in self.layers.iter_mut().enumerate() {
+            if i == active {
+                continue;
+            }
+
+            let rotated = transform::rotate(&layer.pixels, degrees, width, height, background);
+            if resized {
+                layer.history.clear();
+            } else {
+                layer.history.record("Rotate", &layer.pixels, &rotated);
+            }
+            layer.pixels = rotated;
+        }
+
+        (self.width, self.height) = (width, height);
+        self.selection = None;
+    }
+
+    /// Makes another layer the one being drawn on.
+    fn select_layer(&mut self, index: usize) {
+        let active = &mut self.layers[self.active_layer];
+        std::mem::swap(&mut active.pixels, &mut self.canvas.pixels);
+        std::mem::swap(&mut active.history, &mut self.history);
+
+        self.active_layer = index;
+        let active = &mut self.layers[index];
+        std::mem::swap(&mut active.pixels, &mut self.canvas.pixels);
+        std::mem::swap(&mut active.history, &mut self.history);
+    }
+
+    /// Adds a layer of `transparent` cells above the active one
+    /// and selects it.
+    fn add_layer(&mut self, transparent: Rgb8) {
+        let name = format!("Layer {}", self.layers.len() + 1);
+        self.layers.insert(
+            self.active_layer + 1,
+            Layer::new(name, self.width, self.height, transparent),
+        );
+        self.dirty = true;
+        self.select_layer(self.active_layer + 1);
+    }
+
+    /// Blends the active layer into the one below it and removes
+    /// it. Undoing from the lower layer puts the merged one back.
+    fn merge_down(&mut self, transparent: Rgb8) {
+        let active = self.active_layer;
+        if active == 0 {
+            return;
+        }
+
+        let below = Layer::new(String::new(), 0, 0, transparent);
+        let colors = layer::composite(
+            [
+                (self.layers[active - 1].pixels.as_slice(), &below),
+                (self.canvas.pixels.as_slice(), &self.layers[active]),
+            ],
+            self.width,
+            self.height,
+            transparent,
+        );
+
+        self.select_layer(active - 1);
+        let merged = self.layers.remove(active);
+        self.history.begin("Merge Down", &self.canvas.pixels);
+        for (column, colors) in self.canvas.pixels.iter_mut().zip(colors) {
+            for (pixel, color) in column.iter_mut().zip(colors) {
+                pixel.color = color;
+            }
         }
+        self.history.commit_merge(&self.canvas.pixels, merged);
+    }
+
+    /// Reverts the active layer's last operation, returning its label.
+    fn undo(&mut self) -> Option<&'static str> {
+        let above = self.active_layer + 1;
+        self.history
+            .undo(&mut self.canvas.pixels, &mut self.layers, above)
+    }
+
+    /// Reapplies the active layer's last undone operation, returning
+    /// its label.
+    fn redo(&mut self) -> Option<&'static str> {
+        let above = self.active_layer + 1;
+        self.history
+            .redo(&mut self.canvas.pixels, &mut self.layers, above)
+    }
+
+    /// Undoes or redoes the active layer's operations until exactly
+    /// `position` of them are applied.
+    fn jump_history(&mut self, position: usize) {
+        let above = self.active_layer + 1;
+        self.history
+            .jump(&mut self.canvas.pixels, &mut self.layers, above, position);
+    }
+
+    /// Replaces every layer, drawing on the one at `active`.
+    fn set_layers(&mut self, mut layers: Vec<Layer>, active: usize) {
+        self.canvas.pixels = std::mem::take(&mut layers[active].pixels);
+        self.layers = layers;
+        self.active_layer = active;
     }
 }
 
 struct State {
-    pixels: Vec<Vec<Pixel>>,
+    documents: Vec<Document>,
+    active: usize,
     drawing: bool,
     erasing: bool,
     should_reset: bool,
+    should_undo: bool,
+    should_redo: bool,
     should_exit: bool,
     should_calc_positions: bool,
+    should_export_palette: bool,
+    should_export_svg: bool,
+    should_export_ascii: bool,
+    should_export_png: bool,
+    should_copy_image: bool,
+    should_import_image: bool,
+    should_save_project: bool,
+    should_open_project: bool,
+    /// A file picked or dropped to be opened, used by the next
+    /// project open or image import in place of the file name.
+    open_path: Option<PathBuf>,
+    status: Option<String>,
+    /// How many cells the current or last stroke changed.
+    stroke_cells: Option<usize>,
+    stroke_start: Option<(i32, i32)>,
+    last_cell: Option<(i32, i32)>,
+    /// Where the line placed by the next click starts, when
+    /// lines are placed with two clicks.
+    line_start: Option<(i32, i32)>,
+    /// The color a left click stroke paints in toggle mode, picked
+    /// from the cell the stroke started on.
+    toggle_color: Option<Rgb8>,
+    constrain_stroke: bool,
+    last_frame: Instant,
+    /// Where the mouse was last seen and when it got there,
+    /// measured from when the app started.
+    last_mouse: Point2,
+    mouse_moved_at: Duration,
+    /// The cells a fill would change, the hovered cell they
+    /// were worked out for and when that was.
+    fill_preview: Vec<(usize, usize)>,
+    fill_preview_cell: Option<(i32, i32)>,
+    fill_preview_at: Instant,
+    /// How many cells use each color, as last counted.
+    color_counts: Vec<(Rgb8, usize)>,
+    color_counts_at: Option<Instant>,
+    focused: bool,
+    cursor: (usize, usize),
+    lasso: Vec<Point2>,
+    /// Where the stabilizer has the brush this frame, in cells.
+    stabilized: Option<Point2>,
+    should_new_document: bool,
+    should_tween: bool,
+    should_add_layer: bool,
+    should_merge_down: bool,
+    should_export_layers: bool,
+    switch_layer: Option<usize>,
+    jump_history: Option<usize>,
+    should_screenshot: bool,
+    should_paste_palette: bool,
+    drawing_secondary: bool,
+    /// Where the mouse was last seen while panning with the middle button.
+    panning: Option<Point2>,
+    dialog: Option<dialog::Dialog>,
+    confirm_reset: bool,
+    confirm_exit: bool,
+    confirm_rotate: bool,
+    should_rotate: bool,
+    /// The name the current setup is saved as, and the names of
+    /// the saved presets.
+    preset_name: String,
+    presets: Vec<String>,
+    should_save_preset: bool,
+    load_preset: Option<String>,
+    should_close_document: bool,
+    switch_to: Option<usize>,
+    adjust: Option<adjust::Adjustment>,
+    flip: Option<canvas::Flip>,
+    top_inset: f32,
+    pressure: f32,
+    /// The hue the rainbow brush is painting with, in degrees.
+    rainbow_hue: f32,
+}
+
+impl State {
+    /// Whether any mouse button is painting.
+    fn stroking(&self) -> bool {
+        self.drawing || self.erasing || self.drawing_secondary
+    }
+
+    fn document(&self) -> &Document {
+        &self.documents[self.active]
+    }
+
+    fn document_mut(&mut self) -> &mut Document {
+        &mut self.documents[self.active]
+    }
+
+    /// Keeps the keyboard cursor on the active canvas, which
+    /// may have shrunk or been swapped for a smaller one.
+    fn clamp_cursor(&mut self) {
+        let document = self.document();
+        let (last_x, last_y) = (document.width - 1, document.height - 1);
+        self.cursor = (self.cursor.0.min(last_x), self.cursor.1.min(last_y));
+    }
+
+    /// Exits straight away, unless a canvas has unsaved
+    /// changes and the user should be asked first.
+    fn request_exit(&mut self) {
+        if self.documents.iter().any(Document::is_unsaved) {
+            self.confirm_exit = true;
+        } else {
+            self.should_exit = true;
+        }
+    }
 }
 
 struct Settings {
     brush: Brush,
     brush_size: usize,
-    grid_size: usize,
+    eraser_size: usize,
     display_fps: bool,
+    fps_corner: Corner,
+    middle_click: MiddleClick,
+    snap_step: usize,
+    reset_key: ResetKey,
+    frame_cap: FrameCap,
     dark_mode: bool,
+    /// Larger text and controls with stronger outlines.
+    high_contrast: bool,
     primary_color: Rgb8,
     secondary_color: Rgb8,
     primary_color_buf: [u8; 3],
     secondary_color_buf: [u8; 3],
+    background_color: Rgb8,
+    background_color_buf: [u8; 3],
+    fill_color: Rgb8,
+    fill_color_buf: [u8; 3],
+    palette_format: PaletteFormat,
+    palette_skip_background: bool,
+    keyboard_cursor: bool,
+    brush_preview: bool,
+    /// How long the mouse has to stay still before the brush
+    /// preview fades in, in seconds.
+    preview_delay: f32,
+    crisp: bool,
+    origin: Origin,
+    /// Cells between the top left of a larger tile and the canvas.
+    tile_offset: [u32; 2],
+    wand_tolerance: u8,
+    circle_bias: f32,
+    eyedropper_average: bool,
+    export_padding: u32,
+    padding_transparent: bool,
+    padding_color: Rgb8,
+    padding_color_buf: [u8; 3],
+    smooth_export: bool,
+    /// Companion maps written next to PNG exports.
+    normal_map: bool,
+    depth_map: bool,
+    export_scale: u32,
+    /// How much of each cell is left as a gap, as a fraction of its width.
+    cell_gap: f32,
+    ascii_colors: bool,
+    brightness_step: i16,
+    posterize_levels: u8,
+    /// How many cells the edges of a selection fade in over
+    /// when adjusting colors.
+    feather: usize,
+    /// How far to turn the canvas counterclockwise, in degrees.
+    rotate_angle: f32,
+    /// Grow the grid so rotated corners aren't cut off.
+    rotate_grow: bool,
+    dither: bool,
+    dither_mix: f32,
+    /// What every random tool's numbers are drawn from, so the
+    /// same strokes give the same results.
+    seed: u64,
+    rainbow: bool,
+    /// How fast the rainbow brush cycles, in degrees per second.
+    rainbow_speed: f32,
+    line_gradient: bool,
+    /// Place lines with a click at each end instead of by dragging.
+    two_click_line: bool,
+    /// Clear strokes that start on the primary color to the
+    /// background instead of painting over them.
+    toggle_paint: bool,
+    rulers: bool,
+    letterbox: bool,
+    backdrop: Backdrop,
+    guides: bool,
+    /// Outline every rectangle drawn for the canvas, which shows
+    /// how runs of matching cells are merged.
+    cell_outlines: bool,
+    /// Mark cells with no neighbors of the same color.
+    show_orphans: bool,
+    /// How much memory each layer's undo history may use, in MiB.
+    undo_budget: usize,
+    tween_count: usize,
+    tween_discrete: bool,
+    pressure_size: bool,
+    symmetry_segments: usize,
+    export_hidden_layers: bool,
+    swatches: Vec<Rgb8>,
+    extract_swatches: bool,
+    resample: import::Resample,
+    aspect: import::Aspect,
+    swatch_count: usize,
+    paint_behind: bool,
+    paint_on_move: bool,
+    wrap_strokes: bool,
+    /// How far the brush lags behind the mouse, from 0.0 for
+    /// not at all to just under 1.0 for very smooth strokes.
+    stabilizer: f32,
+    /// How far out from the center the brush stays fully opaque.
+    hardness: f32,
+    /// How far each stamp may land from the mouse, in cells.
+    scatter: f32,
+    locked_colors: Vec<Rgb8>,
+    /// Scale the grid's height along with its width, and the
+    /// other way around, so its shape stays the same.
+    lock_aspect: bool,
+}
+
+impl Settings {
+    /// Shrinks the brush and eraser so they never cover more
+    /// than the whole grid, given the length of its longest side.
+    fn clamp_sizes(&mut self, longest: usize) {
+        self.brush_size = self.brush_size.clamp(1, longest);
+        self.eraser_size = self.eraser_size.clamp(1, longest);
+    }
+
+    /// The options PNG exports are written with.
+    fn png_options(&self) -> export::PngOptions {
+        export::PngOptions {
+            padding: self.export_padding,
+            padding_color: (!self.padding_transparent).then_some(self.padding_color),
+            scale: self.export_scale,
+            cell_gap: self.cell_gap,
+            smooth: self.smooth_export,
+            offset: (self.tile_offset[0], self.tile_offset[1]),
+        }
+    }
+
+    /// Converts a grid index to the coordinates shown to the
+    /// user, counting from where the canvas sits in its tile.
+    fn coordinates(&self, x: i32, y: i32, width: usize, height: usize) -> (i32, i32) {
+        let (x, y) = self.origin.coordinates(x, y, width, height);
+        let [offset_x, offset_y] = self.tile_offset.map(|offset| offset as i32);
+        match self.origin {
+            Origin::Center => (x + offset_x, y - offset_y),
+            Origin::TopLeft => (x + offset_x, y + offset_y),
+        }
+    }
+
+    /// Whether cells of this color are protected from being painted over.
+    fn is_locked(&self, color: Rgb8) -> bool {
+        self.locked_colors.contains(&color)
+    }
+
+    /// Puts the brush type and sizes back to their defaults,
+    /// leaving the colors and everything else alone.
+    fn reset_tool(&mut self) {
+        let defaults = Settings::default();
+        self.brush = defaults.brush;
+        self.brush_size = defaults.brush_size;
+        self.eraser_size = defaults.eraser_size;
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            brush: Brush::Square,
+            brush_size: 1,
+            eraser_size: 1,
+            display_fps: true,
+            fps_corner: Corner::TopRight,
+            middle_click: MiddleClick::Paint,
+            snap_step: 1,
+            reset_key: ResetKey::Ctrl,
+            frame_cap: FrameCap::Uncapped,
+            dark_mode: true,
+            high_contrast: false,
+            primary_color: WHITE,
+            secondary_color: BLACK,
+            primary_color_buf: [255; 3],
+            secondary_color_buf: [0; 3],
+            background_color: Pixel::default().color,
+            background_color_buf: [0; 3],
+            fill_color: BLACK,
+            fill_color_buf: [0; 3],
+            palette_format: PaletteFormat::Gpl,
+            palette_skip_background: false,
+            keyboard_cursor: false,
+            brush_preview: true,
+            preview_delay: 0.0,
+            crisp: false,
+            origin: Origin::TopLeft,
+            tile_offset: [0, 0],
+            wand_tolerance: 0,
+            circle_bias: 0.0,
+            eyedropper_average: false,
+            export_padding: 0,
+            padding_transparent: true,
+            padding_color: BLACK,
+            padding_color_buf: [0; 3],
+            smooth_export: false,
+            normal_map: false,
+            depth_map: false,
+            export_scale: 1,
+            cell_gap: 0.0,
+            ascii_colors: false,
+            brightness_step: 16,
+            posterize_levels: 4,
+            feather: 0,
+            rotate_angle: 45.0,
+            rotate_grow: true,
+            dither: false,
+            dither_mix: 0.5,
+            seed: 0,
+            rainbow: false,
+            rainbow_speed: 180.0,
+            line_gradient: false,
+            two_click_line: false,
+            toggle_paint: false,
+            rulers: false,
+            letterbox: false,
+            backdrop: Backdrop::Solid,
+            guides: false,
+            cell_outlines: false,
+            show_orphans: false,
+            undo_budget: history::DEFAULT_BUDGET / (1024 * 1024),
+            tween_count: 3,
+            tween_discrete: false,
+            pressure_size: false,
+            symmetry_segments: 1,
+            export_hidden_layers: false,
+            swatches: Vec::new(),
+            extract_swatches: false,
+            resample: import::Resample::Nearest,
+            aspect: import::Aspect::Fit,
+            swatch_count: 8,
+            paint_behind: false,
+            paint_on_move: true,
+            wrap_strokes: false,
+            stabilizer: 0.0,
+            hardness: 1.0,
+            scatter: 0.0,
+            locked_colors: Vec::new(),
+            lock_aspect: true,
+        }
+    }
 }
 
 struct Model {
     egui: Egui,
     state: State,
     settings: Settings,
+    config: Config,
+    rng: StdRng,
 }
 
 fn main() {
-    nannou::app(model).update(update).run();
+    match Batch::from_args(std::env::args().skip(1)) {
+        Ok(Some(batch)) => {
+            if let Err(e) = batch.run() {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+        }
+        Ok(None) => nannou::app(model).update(update).exit(exit).run(),
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    }
 }
 
 fn model(app: &App) -> Model {
@@ -84,39 +867,144 @@ fn model(app: &App) -> Model {
         .unwrap();
     let window = app.window(window_id).unwrap();
 
+    // Escape cancels two click lines, and quits through `request_exit` otherwise
+    app.set_exit_on_escape(false);
+
     let grid_size = 16usize;
+    let config = Config::load();
+    let fill = config.fill_color.unwrap_or([0; 3]);
+    let defaults = Settings::default();
+    let brush_size = config.brush_size.unwrap_or(defaults.brush_size).max(1);
+    let settings = Settings {
+        fill_color: rgb8(fill[0], fill[1], fill[2]),
+        fill_color_buf: fill,
+        brush: config
+            .brush
+            .as_deref()
+            .map_or(defaults.brush, Brush::from_tag),
+        brush_size,
+        // The eraser starts out matching the brush, as it did
+        // before it had a size of its own
+        eraser_size: brush_size,
+        high_contrast: config.high_contrast,
+        ..defaults
+    };
 
     Model {
         egui: Egui::from_window(&window),
-        settings: Settings {
-            brush: Brush::Square,
-            brush_size: 1,
-            grid_size,
-            display_fps: true,
-            dark_mode: true,
-            primary_color: WHITE,
-            secondary_color: BLACK,
-            primary_color_buf: [255; 3],
-            secondary_color_buf: [0; 3],
-        },
+        rng: StdRng::seed_from_u64(settings.seed),
         state: State {
-            pixels: vec![vec![Pixel::default(); grid_size]; grid_size],
+            documents: vec![Document::new(
+                grid_size,
+                grid_size,
+                String::from("drawing"),
+                settings.fill_color,
+            )],
+            active: 0,
             drawing: false,
             erasing: false,
             should_reset: false,
+            should_undo: false,
+            should_redo: false,
             should_exit: false,
             should_calc_positions: false,
+            should_export_palette: false,
+            should_export_svg: false,
+            should_export_ascii: false,
+            should_export_png: false,
+            should_copy_image: false,
+            should_import_image: false,
+            should_save_project: false,
+            should_open_project: false,
+            open_path: None,
+            status: None,
+            stroke_cells: None,
+            stroke_start: None,
+            last_cell: None,
+            line_start: None,
+            toggle_color: None,
+            constrain_stroke: false,
+            last_frame: Instant::now(),
+            last_mouse: Point2::ZERO,
+            mouse_moved_at: Duration::ZERO,
+            fill_preview: Vec::new(),
+            fill_preview_cell: None,
+            fill_preview_at: Instant::now(),
+            color_counts: Vec::new(),
+            color_counts_at: None,
+            focused: true,
+            cursor: (0, 0),
+            lasso: Vec::new(),
+            stabilized: None,
+            should_new_document: false,
+            should_tween: false,
+            should_add_layer: false,
+            should_merge_down: false,
+            should_export_layers: false,
+            switch_layer: None,
+            jump_history: None,
+            should_screenshot: false,
+            should_paste_palette: false,
+            drawing_secondary: false,
+            panning: None,
+            dialog: None,
+            confirm_reset: false,
+            confirm_exit: false,
+            confirm_rotate: false,
+            should_rotate: false,
+            preset_name: String::new(),
+            presets: preset::list(),
+            should_save_preset: false,
+            load_preset: None,
+            should_close_document: false,
+            switch_to: None,
+            adjust: None,
+            flip: None,
+            top_inset: 0.0,
+            pressure: 1.0,
+            rainbow_hue: 0.0,
         },
+        config,
+        settings,
     }
 }
 
 fn raw_window_event(_app: &App, model: &mut Model, event: &nannou::winit::event::WindowEvent) {
     model.egui.handle_raw_event(event);
+
+    // Track stylus pressure, falling back to full
+    // pressure when the device doesn't report any
+    if let nannou::winit::event::WindowEvent::Touch(touch) = event {
+        model.state.pressure = match (touch.phase, touch.force) {
+            (TouchPhase::Ended | TouchPhase::Cancelled, _) | (_, None) => 1.0,
+            (_, Some(force)) => force.normalized() as f32,
+        };
+    }
 }
 
-fn event(_app: &App, model: &mut Model, event: WindowEvent) {
+fn event(app: &App, model: &mut Model, event: WindowEvent) {
     match event {
         Resized(_) => model.state.should_calc_positions = true,
+        Focused => {
+            model.state.focused = true;
+            app.set_loop_mode(LoopMode::RefreshSync);
+        }
+        DroppedFile(path) => open_file(model, &path),
+        Unfocused => {
+            // Only wake up for events while in the background,
+            // and stop any strokes since we won't see the release
+            model.state.focused = false;
+            let document = model.state.document_mut();
+            document.history.commit(&document.canvas.pixels);
+            model.state.drawing = false;
+            model.state.erasing = false;
+            model.state.drawing_secondary = false;
+            model.state.toggle_color = None;
+            model.state.panning = None;
+            model.state.stroke_start = None;
+            model.state.last_cell = None;
+            app.set_loop_mode(LoopMode::Wait);
+        }
         MousePressed(button) => {
             // Prevent drawing on the GUI
             if model.egui.ctx().is_pointer_over_area() {
@@ -125,142 +1013,1667 @@ fn event(_app: &App, model: &mut Model, event: WindowEvent) {
 
             // Enable drawing or erasing if the user
             // holds down left or right click respectively
-            if let MouseButton::Left = button {
-                model.state.drawing = true;
-            } else if let MouseButton::Right = button {
-                model.state.erasing = true;
+            match (button, model.settings.middle_click) {
+                (MouseButton::Left, _) => model.state.drawing = true,
+                (MouseButton::Right, _) => model.state.erasing = true,
+                (MouseButton::Middle, MiddleClick::Paint) => model.state.drawing_secondary = true,
+                (MouseButton::Middle, MiddleClick::Pan) => {
+                    model.state.panning = Some(app.mouse.position());
+                    return;
+                }
+                _ => return,
+            }
+
+            // Both clicks of a two click line are handled here, so
+            // the usual stroke never starts
+            if model.settings.two_click_line
+                && matches!(model.settings.brush, Brush::Line)
+                && matches!(button, MouseButton::Left | MouseButton::Right)
+            {
+                model.state.drawing = false;
+                model.state.erasing = false;
+
+                let diff = cell_size(app, &model.settings, model.state.document());
+                let cell = snap_cell(
+                    mouse_cell(app, model.state.document(), diff),
+                    model.settings.snap_step,
+                );
+                let Some(start) = model.state.line_start.take() else {
+                    model.state.line_start = Some(cell);
+                    return;
+                };
+
+                let end = if model.state.constrain_stroke {
+                    constrain_line(start, cell)
+                } else {
+                    cell
+                };
+                let color = if button == MouseButton::Left {
+                    model.settings.primary_color
+                } else {
+                    model.settings.secondary_color
+                };
+                let gradient = model.settings.line_gradient && button == MouseButton::Left;
+
+                model.state.stroke_cells = Some(0);
+                let document = model.state.document_mut();
+                document.history.begin("Line", &document.canvas.pixels);
+                draw_line(model, start, end, color, gradient);
+                let document = model.state.document_mut();
+                document.history.commit(&document.canvas.pixels);
+                return;
             }
+
+            // In toggle mode, a stroke clears the primary color if it
+            // starts on it and paints it otherwise, all the way through
+            if model.settings.toggle_paint
+                && button == MouseButton::Left
+                && matches!(model.settings.brush, Brush::Circle | Brush::Square)
+            {
+                let document = model.state.document();
+                let diff = cell_size(app, &model.settings, document);
+                let (x, y) = mouse_cell(app, document, diff);
+                let on_primary = document.canvas.color(x, y) == Some(model.settings.primary_color);
+                model.state.toggle_color = Some(if on_primary {
+                    model.settings.background_color
+                } else {
+                    model.settings.primary_color
+                });
+            }
+
+            model.state.stroke_cells = Some(0);
+
+            // Remember the canvas so the stroke can be undone
+            let label = match model.settings.brush {
+                Brush::Circle | Brush::Square if button != MouseButton::Right => "Draw",
+                Brush::Circle | Brush::Square => "Erase",
+                Brush::Fill => "Fill",
+                Brush::Line => "Line",
+                Brush::Lasso | Brush::Wand | Brush::Eyedropper => return,
+            };
+            let document = model.state.document_mut();
+            document.history.begin(label, &document.canvas.pixels);
         }
         MouseReleased(button) => {
             // Disable drawing or erasing if the user
             // releases left or right click respectively
-            if let MouseButton::Left = button {
-                model.state.drawing = false;
-            } else if let MouseButton::Right = button {
-                model.state.erasing = false;
+            let color = match button {
+                MouseButton::Left => {
+                    model.state.drawing = false;
+                    model.state.toggle_color = None;
+                    model.settings.primary_color
+                }
+                MouseButton::Right => {
+                    model.state.erasing = false;
+                    model.settings.secondary_color
+                }
+                MouseButton::Middle if model.state.panning.is_some() => {
+                    model.state.panning = None;
+                    return;
+                }
+                MouseButton::Middle => {
+                    model.state.drawing_secondary = false;
+                    model.settings.secondary_color
+                }
+                _ => return,
+            };
+
+            if let (Brush::Line, Some(start), Some(end)) = (
+                &model.settings.brush,
+                model.state.stroke_start,
+                model.state.last_cell,
+            ) {
+                let gradient = model.settings.line_gradient && button == MouseButton::Left;
+                draw_line(model, start, end, color, gradient);
+            }
+
+            if let (Brush::Lasso, Some(_)) = (&model.settings.brush, model.state.stroke_start) {
+                let document = model.state.document();
+                let (width, height) = (document.width, document.height);
+                model.state.document_mut().selection =
+                    selection::lasso(&model.state.lasso, width, height);
+                model.state.lasso.clear();
+            }
+
+            if !model.state.stroking() {
+                model.state.stroke_start = None;
+                model.state.last_cell = None;
+                let document = model.state.document_mut();
+                document.history.commit(&document.canvas.pixels);
+            }
+        }
+        MouseWheel(delta, _) => {
+            // Let egui scroll its own windows
+            if model.egui.ctx().is_pointer_over_area() {
+                return;
+            }
+
+            let amount = match delta {
+                MouseScrollDelta::LineDelta(_, y) => y,
+                MouseScrollDelta::PixelDelta(pos) => pos.y as f32,
+            };
+            if amount == 0.0 {
+                return;
+            }
+
+            // Zoom while holding control
+            if app.keys.mods.ctrl() {
+                let document = model.state.document_mut();
+                document.zoom = (document.zoom * 1.25.powf(amount.signum())).clamp(0.25, 16.0);
+                model.state.should_calc_positions = true;
+                return;
             }
+
+            let size = model.settings.brush_size as i32 + amount.signum() as i32;
+            let longest = model.state.document().longest_side();
+            model.settings.brush_size = size.clamp(1, longest as i32) as usize;
         }
+        // Let egui have the keys while typing in a text field
+        KeyPressed(_) if model.egui.ctx().wants_keyboard_input() => (),
         KeyPressed(key) => match key {
             Key::Q => {
-                model.state.should_exit = true;
+                model.state.request_exit();
+            }
+            Key::Escape if model.state.line_start.is_some() => {
+                model.state.line_start = None;
+            }
+            Key::Escape => {
+                model.state.request_exit();
+            }
+            Key::R => match model.settings.reset_key {
+                ResetKey::Immediate => model.state.should_reset = true,
+                ResetKey::Ctrl if app.keys.mods.ctrl() => model.state.should_reset = true,
+                ResetKey::Ctrl => (),
+                ResetKey::Confirm => model.state.confirm_reset = true,
+            },
+            Key::Z if app.keys.mods.ctrl() && app.keys.mods.shift() => {
+                model.state.should_redo = true;
+            }
+            Key::Z if app.keys.mods.ctrl() => {
+                model.state.should_undo = true;
+            }
+            Key::Y if app.keys.mods.ctrl() => {
+                model.state.should_redo = true;
+            }
+            Key::LShift | Key::RShift => {
+                model.state.constrain_stroke = true;
             }
-            Key::R => {
-                model.state.should_reset = true;
+            Key::F12 => {
+                model.state.should_screenshot = true;
+            }
+            Key::Key1
+            | Key::Key2
+            | Key::Key3
+            | Key::Key4
+            | Key::Key5
+            | Key::Key6
+            | Key::Key7
+            | Key::Key8
+            | Key::Key9 => {
+                let slot = SWATCH_KEYS.iter().position(|&swatch| swatch == key);
+                if let Some(&color) = slot.and_then(|slot| model.settings.swatches.get(slot)) {
+                    model.settings.primary_color = color;
+                    model.settings.primary_color_buf = [color.red, color.green, color.blue];
+                }
+            }
+            Key::Left | Key::Right | Key::Down | Key::Up if app.keys.mods.ctrl() => {
+                let pan = &mut model.state.document_mut().pan;
+                match key {
+                    Key::Left => pan.x -= 1.0,
+                    Key::Right => pan.x += 1.0,
+                    Key::Down => pan.y -= 1.0,
+                    _ => pan.y += 1.0,
+                }
+            }
+            Key::Left | Key::Right | Key::Down | Key::Up | Key::Space | Key::Back
+                if model.settings.keyboard_cursor =>
+            {
+                use_cursor(model, key);
             }
             _ => (),
         },
+        KeyReleased(Key::LShift | Key::RShift) => {
+            model.state.constrain_stroke = false;
+        }
         _ => (),
     }
 }
 
-fn update(app: &App, model: &mut Model, update: Update) {
-    let win = app.window_rect();
-    let diff = win.w().min(win.h()) / model.settings.grid_size as f32;
+fn exit(_app: &App, mut model: Model) {
+    remember_tool(&mut model);
+}
 
-    // Reset canvas
-    if model.state.should_reset {
-        model.state.should_reset = false;
-        model.state.should_calc_positions = true;
-        model.state.pixels =
-            vec![vec![Pixel::default(); model.settings.grid_size]; model.settings.grid_size];
+fn update(app: &App, model: &mut Model, update: Update) {
+    // Open a new canvas
+    if model.state.should_new_document {
+        model.state.should_new_document = false;
+        let file_name = format!("drawing{}", model.state.documents.len() + 1);
+        let current = model.state.document();
+        let document = Document::new(
+            current.width,
+            current.height,
+            file_name,
+            model.settings.fill_color,
+        );
+        model.state.documents.push(document);
+        model.state.switch_to = Some(model.state.documents.len() - 1);
     }
 
-    // Recalculate pixel positions
-    if model.state.should_calc_positions {
-        model.state.should_calc_positions = false;
-        for (x, row) in model.state.pixels.iter_mut().enumerate() {
-            for (y, pixel) in row.iter_mut().enumerate() {
-                let h = (model.settings.grid_size / 2) as f32;
-                let new_x = (x as f32 - (h - 0.5)) * diff;
-                let new_y = (y as f32 - (h - 0.5)) * diff;
-                pixel.x = new_x;
-                pixel.y = new_y;
-            }
+    // Insert canvases blending the current one into the next
+    if model.state.should_tween {
+        model.state.should_tween = false;
+        let active = model.state.active;
+        let (from, to) = (
+            &model.state.documents[active],
+            &model.state.documents[active + 1],
+        );
+        let frames = tween::in_betweens(
+            &from.canvas.pixels,
+            &to.canvas.pixels,
+            model.settings.tween_count,
+            model.settings.tween_discrete,
+        );
+        let file_name = from.file_name.clone();
+        let (width, height) = (from.width, from.height);
+        for (i, pixels) in frames.into_iter().enumerate() {
+            let name = format!("{file_name}_tween{}", i + 1);
+            let mut document = Document::new(width, height, name, model.settings.fill_color);
+            document.canvas.pixels = pixels;
+            document.dirty = true;
+            model.state.documents.insert(active + 1 + i, document);
         }
+        model.state.status = Some(format!(
+            "Generated {} in-betweens",
+            model.settings.tween_count
+        ));
     }
 
-    // Exit program
-    if model.state.should_exit {
-        std::process::exit(0);
+    // Close the current canvas, keeping at least one open
+    if model.state.should_close_document {
+        model.state.should_close_document = false;
+        if model.state.documents.len() > 1 {
+            let active = model.state.active;
+            model.state.documents.remove(active);
+            model.state.switch_to = Some(active.min(model.state.documents.len() - 1));
+        }
     }
 
-    if model.state.drawing || model.state.erasing {
-        let h = (model.settings.grid_size / 2) as f32;
-        let pos_x = (app.mouse.position().x / diff).floor() + h;
-        let pos_y = (app.mouse.position().y / diff).floor() + h;
-        let color = if model.state.drawing {
-            model.settings.primary_color
-        } else {
-            model.settings.secondary_color
-        };
+    // Switch to another canvas
+    if let Some(index) = model.state.switch_to.take() {
+        model.state.active = index;
+        let longest = model.state.document().longest_side();
+        model.settings.clamp_sizes(longest);
+        model.state.clamp_cursor();
+        model.state.lasso.clear();
+        model.state.should_calc_positions = true;
+    }
 
-        match model.settings.brush {
-            Brush::Square => {
-                for x in (pos_x - (model.settings.brush_size as f32 / 2.0))
-                    .ceil()
-                    .clamp(0.0, f32::MAX) as usize
-                    ..(pos_x + (model.settings.brush_size as f32 / 2.0).ceil())
-                        .clamp(0.0, model.settings.grid_size as f32) as usize
-                {
-                    for y in (pos_y - (model.settings.brush_size as f32 / 2.0))
-                        .ceil()
-                        .clamp(0.0, f32::MAX) as usize
-                        ..(pos_y + (model.settings.brush_size as f32 / 2.0).ceil())
-                            .clamp(0.0, model.settings.grid_size as f32)
-                            as usize
-                    {
-                        model.state.pixels[x][y].color = color;
-                    }
-                }
-            }
-            Brush::Circle => {
-                for (x, y) in calc_circle_pixels(model.settings.brush_size as i32) {
-                    model.state.pixels[(x + pos_x as i32)
-                        .clamp(0, model.settings.grid_size as i32 - 1)
-                        as usize][(y + pos_y as i32)
-                        .clamp(0, model.settings.grid_size as i32 - 1)
-                        as usize]
-                        .color = color;
-                }
-            }
+    // Use the file picked in the dialog once it closes
+    if let Some(result) = model.state.dialog.as_ref().and_then(|d| d.result()) {
+        model.state.dialog = None;
+        match result {
+            Ok(Some(path)) => open_file(model, &path),
+            Ok(None) => (),
+            Err(e) => model.state.status = Some(format!("Failed to open file dialog: {e}")),
         }
     }
 
-    // Draw egui elements
-    let egui = &mut model.egui;
-    egui.set_elapsed_time(update.since_start);
-    let ctx = egui.begin_frame();
-    ctx.style_mut(|style| {
-        style.visuals.window_shadow = Shadow::NONE;
-        style.visuals = if model.settings.dark_mode {
-            Visuals::dark()
-        } else {
-            Visuals::light()
-        };
-    });
+    // Add a layer or draw on another one
+    if model.state.should_add_layer {
+        model.state.should_add_layer = false;
+        let transparent = model.settings.background_color;
+        model.state.document_mut().add_layer(transparent);
+        model.state.should_calc_positions = true;
+    }
 
-    if model.settings.display_fps {
+    if model.state.should_merge_down {
+        model.state.should_merge_down = false;
+        let transparent = model.settings.background_color;
+        model.state.document_mut().merge_down(transparent);
+        model.state.should_calc_positions = true;
+    }
+
+    if let Some(index) = model.state.switch_layer.take() {
+        model.state.document_mut().select_layer(index);
+        model.state.should_calc_positions = true;
+    }
+
+    let diff = cell_size(app, &model.settings, model.state.document());
+
+    // Note when the mouse last moved, so a delayed preview
+    // can wait for it to settle
+    let mouse = app.mouse.position();
+    if mouse != model.state.last_mouse {
+        model.state.last_mouse = mouse;
+        model.state.mouse_moved_at = update.since_start;
+    }
+
+    // Drop a half placed line once it can no longer be finished
+    if !model.settings.two_click_line || !matches!(model.settings.brush, Brush::Line) {
+        model.state.line_start = None;
+    }
+
+    // Reset canvas
+    if model.state.should_reset {
+        model.state.should_reset = false;
+        model.state.should_calc_positions = true;
+        let document = &mut model.state.documents[model.state.active];
+        if let Some(message) = reset_canvas(document, &mut model.settings) {
+            model.state.status = Some(message);
+        }
+        model.state.clamp_cursor();
+    }
+
+    // Forget the oldest operations once the history outgrows its budget
+    let budget = model.settings.undo_budget * 1024 * 1024;
+    let document = model.state.document_mut();
+    document.history.set_budget(budget);
+    for layer in &mut document.layers {
+        layer.history.set_budget(budget);
+    }
+
+    // Undo or redo the last operation
+    if model.state.should_undo {
+        model.state.should_undo = false;
+        if let Some(label) = model.state.document_mut().undo() {
+            model.state.status = Some(format!("Undid {label}"));
+        }
+    }
+
+    if model.state.should_redo {
+        model.state.should_redo = false;
+        if let Some(label) = model.state.document_mut().redo() {
+            model.state.status = Some(format!("Redid {label}"));
+        }
+    }
+
+    // The grid may have shrunk since the sizes were last set
+    model
+        .settings
+        .clamp_sizes(model.state.document().longest_side());
+
+    if let Some(position) = model.state.jump_history.take() {
+        model.state.document_mut().jump_history(position);
+    }
+
+    // Recalculate pixel positions
+    if model.state.should_calc_positions {
+        model.state.should_calc_positions = false;
+        let document = model.state.document_mut();
+        let (width, height) = (document.width, document.height);
+        for (x, row) in document.canvas.pixels.iter_mut().enumerate() {
+            for (y, pixel) in row.iter_mut().enumerate() {
+                let center = cell_center(x as i32, y as i32, width, height, diff);
+                pixel.x = center.x;
+                pixel.y = center.y;
+            }
+        }
+    }
+
+    // Export the colors in the canvas as a palette
+    if model.state.should_export_palette {
+        model.state.should_export_palette = false;
+        let skip = if model.settings.palette_skip_background {
+            Some(model.settings.background_color)
+        } else {
+            None
+        };
+        let pixels = model
+            .state
+            .document()
+            .flatten(model.settings.background_color);
+        let colors =
+            palette::collect_colors(pixels.iter().flatten().map(|pixel| &pixel.color), skip);
+        let extension = model.settings.palette_format.extension();
+        let path = model.state.document().path(&format!(".{extension}"));
+        model.state.status = Some(
+            match palette::write_palette(&path, &colors, model.settings.palette_format) {
+                Ok(()) => format!("Exported {} colors to {}", colors.len(), path.display()),
+                Err(e) => format!("Failed to export palette: {e}"),
+            },
+        );
+    }
+
+    // Save the canvas as a project
+    if model.state.should_save_project {
+        model.state.should_save_project = false;
+        let document = model.state.document();
+        let path = document.path(".apd");
+        let view = project::View {
+            zoom: document.zoom,
+            pan: document.pan.to_array(),
+        };
+        let pixels = document.flatten(model.settings.background_color);
+        let saved = project::save(
+            &path,
+            &pixels,
+            document.layer_stack(),
+            document.active_layer,
+            view,
+        );
+        model.state.status = Some(match saved {
+            Ok(()) => {
+                model.state.document_mut().mark_saved();
+                remember_project(model, path.clone());
+                format!("Saved project to {}", path.display())
+            }
+            Err(e) => format!("Failed to save project: {e}"),
+        });
+    }
+
+    // Replace the canvas with a saved project
+    if model.state.should_open_project {
+        model.state.should_open_project = false;
+        let path = match model.state.open_path.take() {
+            Some(path) => path,
+            None => model.state.document().path(".apd"),
+        };
+        model.state.status = Some(match project::open(&path) {
+            Ok(opened) => {
+                // Older projects have no view, so fit them to the window
+                let view = opened.view.unwrap_or(project::View {
+                    zoom: 1.0,
+                    pan: [0.0, 0.0],
+                });
+                model.state.document_mut().zoom = view.zoom;
+                model.state.document_mut().pan = Point2::from(view.pan);
+                let document = model.state.document_mut();
+                (document.width, document.height) = (opened.width, opened.height);
+                model.settings.clamp_sizes(opened.width.max(opened.height));
+                model
+                    .state
+                    .document_mut()
+                    .set_layers(opened.layers, opened.active_layer);
+                model.state.document_mut().selection = None;
+                model.state.document_mut().history.clear();
+                model.state.document_mut().mark_saved();
+                model.state.clamp_cursor();
+                model.state.should_calc_positions = true;
+                remember_project(model, path.clone());
+                format!("Opened {}", path.display())
+            }
+            Err(e) => {
+                model.config.recent.retain(|recent| recent.exists());
+                format!("Failed to open project: {e}")
+            }
+        });
+    }
+
+    // Replace the swatches with hex colors from the clipboard
+    if model.state.should_paste_palette {
+        model.state.should_paste_palette = false;
+        model.state.status = Some(match clipboard::read_text() {
+            Ok(text) => {
+                let colors = palette::parse_hex_list(&text);
+                if colors.is_empty() {
+                    String::from("No hex colors found in the clipboard")
+                } else {
+                    let message = format!("Pasted {} colors", colors.len());
+                    model.settings.swatches = colors;
+                    message
+                }
+            }
+            Err(e) => format!("Failed to paste palette: {e}"),
+        });
+    }
+
+    // Adjust the colors, keeping to the selection if there is one
+    if let Some(adjustment) = model.state.adjust.take() {
+        let settings = &model.settings;
+        let document = &mut model.state.documents[model.state.active];
+        let weights = document
+            .selection
+            .as_ref()
+            .map(|mask| selection::feather(mask, settings.feather));
+        // Filling paints over the canvas, so it keeps off locked colors
+        let locked =
+            |color| matches!(adjustment, adjust::Adjustment::Fill(_)) && settings.is_locked(color);
+        document
+            .history
+            .begin(adjustment.label(), &document.canvas.pixels);
+        adjust::adjust(
+            &mut document.canvas.pixels,
+            adjustment,
+            weights.as_ref(),
+            locked,
+        );
+        document.history.commit(&document.canvas.pixels);
+    }
+
+    // Save the current drawing setup as a preset
+    if model.state.should_save_preset {
+        model.state.should_save_preset = false;
+        let color = |color: Rgb8| [color.red, color.green, color.blue];
+        let settings = &model.settings;
+        let setup = preset::Preset {
+            brush: Some(settings.brush.tag().to_string()),
+            brush_size: Some(settings.brush_size),
+            primary_color: Some(color(settings.primary_color)),
+            secondary_color: Some(color(settings.secondary_color)),
+            swatches: Some(settings.swatches.iter().copied().map(color).collect()),
+            grid_size: Some(model.state.document().width),
+            grid_height: Some(model.state.document().height),
+            symmetry_segments: Some(settings.symmetry_segments),
+        };
+        let name = model.state.preset_name.trim().to_string();
+        model.state.status = Some(match preset::save(&name, &setup) {
+            Ok(()) => {
+                model.state.presets = preset::list();
+                format!("Saved preset {name}")
+            }
+            Err(e) => format!("Failed to save preset: {e}"),
+        });
+    }
+
+    // Switch to a saved drawing setup
+    if let Some(name) = model.state.load_preset.take() {
+        model.state.status = Some(match preset::load(&name) {
+            Ok(setup) => {
+                let settings = &mut model.settings;
+                if let Some(brush) = setup.brush {
+                    settings.brush = Brush::from_tag(&brush);
+                }
+                if let Some(size) = setup.brush_size {
+                    settings.brush_size = size.max(1);
+                }
+                if let Some([r, g, b]) = setup.primary_color {
+                    settings.primary_color = rgb8(r, g, b);
+                    settings.primary_color_buf = [r, g, b];
+                }
+                if let Some([r, g, b]) = setup.secondary_color {
+                    settings.secondary_color = rgb8(r, g, b);
+                    settings.secondary_color_buf = [r, g, b];
+                }
+                if let Some(swatches) = setup.swatches {
+                    settings.swatches = swatches
+                        .into_iter()
+                        .map(|[r, g, b]| rgb8(r, g, b))
+                        .collect();
+                }
+                if let Some(segments) = setup.symmetry_segments {
+                    settings.symmetry_segments = segments.clamp(1, 12);
+                }
+
+                // Resizing clears the canvas, as it does from the settings
+                if let Some(width) = setup.grid_size {
+                    let height = setup.grid_height.unwrap_or(width).clamp(1, 64);
+                    let size = (width.clamp(1, 64), height);
+                    let document = model.state.document_mut();
+                    if size != (document.width, document.height) {
+                        (document.width, document.height) = size;
+                        model.state.should_reset = true;
+                    }
+                }
+                model.state.preset_name = name.clone();
+                format!("Loaded preset {name}")
+            }
+            Err(e) => format!("Failed to load preset: {e}"),
+        });
+    }
+
+    // Mirror the active layer
+    if let Some(flip) = model.state.flip.take() {
+        let label = match flip {
+            canvas::Flip::Horizontal => "Flip Horizontally",
+            canvas::Flip::Vertical => "Flip Vertically",
+        };
+        let document = model.state.document_mut();
+        document.history.begin(label, &document.canvas.pixels);
+        document.canvas.flip(flip);
+        document.history.commit(&document.canvas.pixels);
+    }
+
+    // Turn the canvas by an arbitrary angle
+    if model.state.should_rotate {
+        model.state.should_rotate = false;
+        let degrees = model.settings.rotate_angle;
+        model.state.document_mut().rotate(
+            degrees,
+            model.settings.rotate_grow,
+            model.settings.background_color,
+        );
+        model.state.should_calc_positions = true;
+        let document = model.state.document();
+        model.state.status = Some(format!(
+            "Rotated by {degrees}°, the grid is {} by {} cells",
+            document.width, document.height
+        ));
+    }
+
+    // Export the canvas as a PNG
+    if model.state.should_export_png {
+        model.state.should_export_png = false;
+        let path = model.state.document().path(".png");
+        let pixels = model
+            .state
+            .document()
+            .flatten(model.settings.background_color);
+        let options = model.settings.png_options();
+
+        // Companion maps share the export's size and padding
+        let companions = [
+            (export::Companion::Normal, model.settings.normal_map),
+            (export::Companion::Depth, model.settings.depth_map),
+        ];
+        let result = export::write_png(&path, &pixels, &options).and_then(|()| {
+            for (companion, _) in companions.iter().filter(|(_, enabled)| *enabled) {
+                let path = model
+                    .state
+                    .document()
+                    .path(&format!("_{}.png", companion.suffix()));
+                export::write_png(&path, &companion.render(&pixels), &options)?;
+            }
+            Ok(())
+        });
+        model.state.status = Some(match result {
+            Ok(()) => format!("Exported canvas to {}", path.display()),
+            Err(e) => format!("Failed to export PNG: {e}"),
+        });
+    }
+
+    // Copy the canvas as it would be exported
+    if model.state.should_copy_image {
+        model.state.should_copy_image = false;
+        let pixels = model
+            .state
+            .document()
+            .flatten(model.settings.background_color);
+        let result = export::encode_png(&pixels, &model.settings.png_options())
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| clipboard::write_png(&bytes).map_err(|e| e.to_string()));
+        model.state.status = Some(match result {
+            Ok(()) => String::from("Copied canvas to the clipboard"),
+            Err(e) => format!("Failed to copy image: {e}"),
+        });
+    }
+
+    // Export every layer on its own, plus all of them combined
+    if model.state.should_export_layers {
+        model.state.should_export_layers = false;
+        let document = model.state.document();
+        let options = model.settings.png_options();
+        let composite = document.path("_composite.png");
+        let mut result = export::write_png(
+            &composite,
+            &document.flatten(model.settings.background_color),
+            &options,
+        );
+
+        let mut count = 0;
+        for (i, (pixels, layer)) in document.layer_stack().enumerate() {
+            if result.is_err() || !(layer.visible || model.settings.export_hidden_layers) {
+                continue;
+            }
+
+            let path = document.path(&format!("_layer{}.png", i + 1));
+            result = export::write_png(&path, pixels, &options);
+            count += 1;
+        }
+
+        model.state.status = Some(match result {
+            Ok(()) => format!("Exported {count} layers and {}", composite.display()),
+            Err(e) => format!("Failed to export layers: {e}"),
+        });
+    }
+
+    // Capture the whole window, UI included, once it is next drawn
+    if model.state.should_screenshot {
+        model.state.should_screenshot = false;
+        let time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let path = PathBuf::from(format!("screenshot-{}.png", time.as_secs()));
+        app.main_window().capture_frame(&path);
+        model.state.status = Some(format!("Saved screenshot to {}", path.display()));
+    }
+
+    // Load an image into the canvas
+    if model.state.should_import_image {
+        model.state.should_import_image = false;
+        let path = match model.state.open_path.take() {
+            Some(path) => path,
+            None => model.state.document().path(".png"),
+        };
+        model.state.status = Some(
+            match import::load_image(
+                &path,
+                model.state.document().width,
+                model.state.document().height,
+                model.settings.resample,
+                model.settings.aspect,
+                model.settings.background_color,
+            ) {
+                Ok(pixels) => {
+                    if model.settings.extract_swatches {
+                        let colors: Vec<Rgb8> =
+                            pixels.iter().flatten().map(|pixel| pixel.color).collect();
+                        model.settings.swatches =
+                            palette::median_cut(&colors, model.settings.swatch_count);
+                    }
+
+                    let document = model.state.document_mut();
+                    document
+                        .history
+                        .record("Import", &document.canvas.pixels, &pixels);
+                    document.canvas.pixels = pixels;
+                    model.state.should_calc_positions = true;
+                    format!("Imported {}", path.display())
+                }
+                Err(e) => format!("Failed to import image: {e}"),
+            },
+        );
+    }
+
+    // Export the canvas as an SVG
+    if model.state.should_export_svg {
+        model.state.should_export_svg = false;
+        let path = model.state.document().path(".svg");
+        model.state.status = Some(
+            match export::write_svg(
+                &path,
+                &model
+                    .state
+                    .document()
+                    .flatten(model.settings.background_color),
+                Some(model.settings.background_color),
+            ) {
+                Ok(()) => format!("Exported canvas to {}", path.display()),
+                Err(e) => format!("Failed to export SVG: {e}"),
+            },
+        );
+    }
+
+    // Export the canvas as text
+    if model.state.should_export_ascii {
+        model.state.should_export_ascii = false;
+        let path = model.state.document().path(".txt");
+        model.state.status = Some(
+            match export::write_ascii(
+                &path,
+                &model
+                    .state
+                    .document()
+                    .flatten(model.settings.background_color),
+                model.settings.ascii_colors,
+            ) {
+                Ok(()) => format!("Exported canvas to {}", path.display()),
+                Err(e) => format!("Failed to export ASCII: {e}"),
+            },
+        );
+    }
+
+    // Exit program by closing the window and letting nannou
+    // shut down, which runs `exit` to save anything worth keeping
+    if model.state.should_exit {
+        model.state.should_exit = false;
+        app.quit();
+        return;
+    }
+
+    // Work out what a fill under the mouse would cover, at most
+    // once per interval however quickly the mouse moves
+    if let Brush::Fill = model.settings.brush {
+        let document = model.state.document();
+        let (x, y) = mouse_cell(app, document, diff);
+        let stale = model.state.fill_preview_at.elapsed() >= FILL_PREVIEW_INTERVAL;
+        if stale && model.settings.brush_preview {
+            model.state.fill_preview = match document.canvas.index(x, y) {
+                Some((x, y)) => document.canvas.region(x, y, 0, document.selection.as_ref()),
+                None => Vec::new(),
+            };
+            model.state.fill_preview_cell = Some((x, y));
+            model.state.fill_preview_at = Instant::now();
+        }
+    } else {
+        model.state.fill_preview.clear();
+        model.state.fill_preview_cell = None;
+    }
+
+    // Drag the canvas along with the mouse
+    if let Some(last) = model.state.panning {
+        let mouse = app.mouse.position();
+        model.state.document_mut().pan += (mouse - last) / diff;
+        model.state.panning = Some(mouse);
+    }
+
+    if model.state.stroking() {
+        let (width, height) = (model.state.document().width, model.state.document().height);
+
+        // Trail the mouse to steady shaky strokes, starting
+        // each new stroke right under the cursor
+        let point = mouse_point(app, model.state.document(), diff);
+        let point = match (model.state.stroke_start, model.state.stabilized) {
+            (Some(_), Some(last)) if model.settings.brush.is_continuous() => {
+                last.lerp(point, 1.0 - model.settings.stabilizer)
+            }
+            _ => point,
+        };
+        model.state.stabilized = Some(point);
+        let cell = point_cell(point);
+        let cell = match model.settings.brush {
+            Brush::Line => snap_cell(cell, model.settings.snap_step),
+            _ => cell,
+        };
+        let start = *model.state.stroke_start.get_or_insert(cell);
+        let target = if model.state.constrain_stroke {
+            constrain_line(start, cell)
+        } else {
+            cell
+        };
+        let (color, size) = if model.state.drawing {
+            let color = model
+                .state
+                .toggle_color
+                .unwrap_or(model.settings.primary_color);
+            (color, model.settings.brush_size)
+        } else if model.state.drawing_secondary {
+            (model.settings.secondary_color, model.settings.brush_size)
+        } else {
+            (model.settings.secondary_color, model.settings.eraser_size)
+        };
+
+        match model.settings.brush {
+            // Fill in the cells skipped between frames so
+            // fast strokes don't leave gaps
+            Brush::Circle | Brush::Square => {
+                let size = if model.settings.pressure_size {
+                    ((size as f32 * model.state.pressure).round() as usize).max(1)
+                } else {
+                    size
+                };
+                // The rainbow brush shifts hue for as long as it is held
+                let color = if model.state.drawing
+                    && model.settings.rainbow
+                    && model.state.toggle_color.is_none()
+                {
+                    model.state.rainbow_hue +=
+                        model.settings.rainbow_speed * update.since_last.as_secs_f32();
+                    model.state.rainbow_hue %= 360.0;
+                    palette::hsv_to_rgb(model.state.rainbow_hue, 1.0, 1.0)
+                } else {
+                    color
+                };
+
+                // Holding the mouse still would only paint the same cells again
+                let still = model.state.last_cell == Some(target);
+                let mut cells = if still && model.settings.paint_on_move {
+                    Vec::new()
+                } else {
+                    calc_line_pixels(model.state.last_cell.unwrap_or(target), target)
+                };
+
+                // Scatter each stamp somewhere within a circle around it
+                let scatter = model.settings.scatter;
+                if scatter > 0.0 {
+                    for (x, y) in &mut cells {
+                        let angle = model.rng.gen::<f32>() * f32::consts::TAU;
+                        let distance = model.rng.gen::<f32>().sqrt() * scatter;
+                        *x += (angle.cos() * distance).round() as i32;
+                        *y += (angle.sin() * distance).round() as i32;
+                    }
+                }
+
+                let dither = model.state.drawing && model.settings.dither;
+                let rng = &mut model.rng;
+                let mut next_color = || {
+                    if dither && rng.gen::<f32>() >= model.settings.dither_mix {
+                        model.settings.secondary_color
+                    } else {
+                        color
+                    }
+                };
+                let mut changed = 0;
+                for (x, y) in cells {
+                    changed += paint(
+                        &mut model.state.document_mut().canvas,
+                        &model.settings,
+                        size,
+                        x,
+                        y,
+                        &mut next_color,
+                    );
+                }
+                *model.state.stroke_cells.get_or_insert(0) += changed;
+            }
+            Brush::Fill => {
+                let (x, y) = target;
+                if model.state.last_cell.is_none() {
+                    let document = &mut model.state.documents[model.state.active];
+                    let changed = fill_cell(document, &model.settings, x, y, color);
+                    *model.state.stroke_cells.get_or_insert(0) += changed;
+                }
+            }
+            // Lines are previewed while dragging and
+            // only placed once the mouse is released
+            Brush::Line => (),
+            Brush::Wand => {
+                let (x, y) = target;
+                let canvas = &model.state.document().canvas;
+                if let (None, Some((x, y))) = (model.state.last_cell, canvas.index(x, y)) {
+                    let region = canvas.region(x, y, model.settings.wand_tolerance, None);
+                    model.state.document_mut().selection =
+                        Some(selection::from_cells(region, width, height));
+                }
+            }
+            // Pick up the color as it looks with every layer shown
+            Brush::Eyedropper => {
+                let (x, y) = target;
+                let document = model.state.document();
+                if model.state.last_cell.is_none() && document.canvas.index(x, y).is_some() {
+                    let canvas = Canvas {
+                        pixels: document.flatten(model.settings.background_color),
+                    };
+                    let colors: Vec<Rgb8> = eyedropper_cells(&model.settings, size, x, y)
+                        .into_iter()
+                        .filter_map(|(x, y)| canvas.color(x, y))
+                        .collect();
+                    let color = palette::average(&colors);
+                    let buf = [color.red, color.green, color.blue];
+                    if model.state.drawing {
+                        model.settings.primary_color = color;
+                        model.settings.primary_color_buf = buf;
+                    } else {
+                        model.settings.secondary_color = color;
+                        model.settings.secondary_color_buf = buf;
+                    }
+                }
+            }
+            Brush::Lasso => {
+                let point = mouse_point(app, model.state.document(), diff);
+                if model.state.lasso.last() != Some(&point) {
+                    model.state.lasso.push(point);
+                }
+            }
+        }
+        model.state.last_cell = Some(target);
+    }
+
+    // Draw egui elements
+    let egui = &mut model.egui;
+    egui.set_elapsed_time(update.since_start);
+    let ctx = egui.begin_frame();
+    ctx.style_mut(|style| {
+        style.visuals.window_shadow = Shadow::NONE;
+        style.visuals = if model.settings.dark_mode {
+            Visuals::dark()
+        } else {
+            Visuals::light()
+        };
+        if model.settings.high_contrast {
+            high_contrast(style, model.settings.dark_mode);
+        }
+    });
+
+    if model.settings.display_fps {
         egui::Window::new("fps")
             .title_bar(false)
             .interactable(false)
             .resizable(false)
-            .anchor(egui::Align2::RIGHT_TOP, Vec2::new(0.0, 0.0))
+            .anchor(model.settings.fps_corner.align(), Vec2::new(0.0, 0.0))
             .show(&ctx, |ui| ui.label(app.fps().round().to_string()));
     }
 
+    // Remember how much of the window the tabs cover so the
+    // rulers can be drawn below them
+    model.state.top_inset = egui::TopBottomPanel::top("documents")
+        .show(&ctx, |ui| {
+            ui.horizontal(|ui| {
+                for (i, document) in model.state.documents.iter().enumerate() {
+                    let tab_clicked = ui
+                        .selectable_label(i == model.state.active, &document.file_name)
+                        .clicked();
+                    if tab_clicked {
+                        model.state.switch_to = Some(i);
+                    }
+                }
+
+                let new_clicked = ui.button("+").on_hover_text("New Canvas").clicked();
+                if new_clicked {
+                    model.state.should_new_document = true;
+                }
+
+                let close_clicked = ui
+                    .add_enabled(model.state.documents.len() > 1, egui::Button::new("x"))
+                    .on_hover_text("Close Canvas")
+                    .clicked();
+                if close_clicked {
+                    model.state.should_close_document = true;
+                }
+            });
+        })
+        .response
+        .rect
+        .height();
+
+    // Show the color of the hovered cell
+    let idle = model.state.focused && !model.state.stroking();
+    if idle && !ctx.is_pointer_over_area() {
+        let document = model.state.document();
+        let (x, y) = mouse_cell(app, document, diff);
+        // Show the color as it looks with every layer, like the eyedropper
+        let color = document
+            .canvas
+            .index(x, y)
+            .map(|(i, j)| document.flatten(model.settings.background_color)[i][j].color);
+        if let Some(color) = color {
+            if color != model.settings.background_color {
+                let (x, y) = model
+                    .settings
+                    .coordinates(x, y, document.width, document.height);
+                egui::show_tooltip_at_pointer(&ctx, egui::Id::new("pixel_tooltip"), |ui| {
+                    ui.label(format!("#{} ({x}, {y})", palette::to_hex(color)));
+                });
+            }
+        }
+    }
+
+    if model.state.confirm_reset {
+        egui::Window::new("Reset Canvas?")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, Vec2::ZERO)
+            .show(&ctx, |ui| {
+                ui.label("This clears the whole canvas.");
+                ui.horizontal(|ui| {
+                    let reset_clicked = ui.button("Reset").clicked();
+                    if reset_clicked {
+                        model.state.should_reset = true;
+                        model.state.confirm_reset = false;
+                    }
+
+                    let cancel_clicked = ui.button("Cancel").clicked();
+                    if cancel_clicked {
+                        model.state.confirm_reset = false;
+                    }
+                });
+            });
+    }
+
+    if model.state.confirm_rotate {
+        egui::Window::new("Rotate by Angle")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, Vec2::ZERO)
+            .show(&ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Angle");
+                    ui.add(
+                        egui::DragValue::new(&mut model.settings.rotate_angle)
+                            .clamp_range(-180.0..=180.0)
+                            .suffix("°"),
+                    )
+                    .on_hover_text("Positive angles turn counterclockwise");
+                });
+                ui.checkbox(&mut model.settings.rotate_grow, "Grow Grid to Fit")
+                    .on_hover_text("Makes the grid larger so the corners aren't cut off");
+
+                if model.settings.rotate_angle % 90.0 != 0.0 {
+                    ui.colored_label(
+                        egui::Color32::YELLOW,
+                        "Angles other than quarter turns are lossy for pixel art",
+                    );
+                }
+                ui.horizontal(|ui| {
+                    let rotate_clicked = ui.button("Rotate").clicked();
+                    if rotate_clicked {
+                        model.state.should_rotate = true;
+                        model.state.confirm_rotate = false;
+                    }
+
+                    let cancel_clicked = ui.button("Cancel").clicked();
+                    if cancel_clicked {
+                        model.state.confirm_rotate = false;
+                    }
+                });
+            });
+    }
+
+    if model.state.confirm_exit {
+        egui::Window::new("Unsaved Changes")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, Vec2::ZERO)
+            .show(&ctx, |ui| {
+                ui.label("Some canvases have changes that haven't been saved.");
+                ui.horizontal(|ui| {
+                    let exit_anyway_clicked = ui.button("Exit Anyway").clicked();
+                    if exit_anyway_clicked {
+                        model.state.should_exit = true;
+                        model.state.confirm_exit = false;
+                    }
+
+                    let cancel_clicked = ui.button("Cancel").clicked();
+                    if cancel_clicked {
+                        model.state.confirm_exit = false;
+                    }
+                });
+            });
+    }
+
     egui::Window::new("Actions").show(&ctx, |ui| {
         let reset_clicked = ui.button("Reset Canvas").clicked();
         if reset_clicked {
             model.state.should_reset = true;
         }
 
+        let fill_canvas_clicked = ui
+            .button("Fill Canvas")
+            .on_hover_text("Fills the selection, or the whole canvas, with the primary color")
+            .clicked();
+        if fill_canvas_clicked {
+            model.state.adjust = Some(adjust::Adjustment::Fill(model.settings.primary_color));
+        }
+
+        ui.horizontal(|ui| {
+            let history = &model.state.document().history;
+            let (undo, redo) = (history.next_undo(), history.next_redo());
+
+            let undo_clicked = ui
+                .add_enabled(undo.is_some(), egui::Button::new("Undo"))
+                .on_hover_text(format!("Undo: {}", undo.unwrap_or("Nothing")))
+                .clicked();
+            if undo_clicked {
+                model.state.should_undo = true;
+            }
+
+            let redo_clicked = ui
+                .add_enabled(redo.is_some(), egui::Button::new("Redo"))
+                .on_hover_text(format!("Redo: {}", redo.unwrap_or("Nothing")))
+                .clicked();
+            if redo_clicked {
+                model.state.should_redo = true;
+            }
+        });
+
         let exit_clicked = ui.button("Exit").clicked();
         if exit_clicked {
-            model.state.should_exit = true;
+            model.state.request_exit();
+        }
+
+        let clear_selection_clicked = ui
+            .add_enabled(
+                model.state.document().selection.is_some(),
+                egui::Button::new("Clear Selection"),
+            )
+            .clicked();
+        if clear_selection_clicked {
+            model.state.document_mut().selection = None;
+        }
+
+        ui.label("Adjust Colors")
+            .on_hover_text("Only changes the selected cells if there is a selection");
+        ui.horizontal(|ui| {
+            ui.label("Feather");
+            ui.add(
+                egui::DragValue::new(&mut model.settings.feather)
+                    .clamp_range(0..=8)
+                    .suffix(" cells"),
+            )
+            .on_hover_text("Blends adjustments in gradually from the edges of the selection");
+        });
+        ui.horizontal(|ui| {
+            let invert_clicked = ui.button("Invert").clicked();
+            if invert_clicked {
+                model.state.adjust = Some(adjust::Adjustment::Invert);
+            }
+
+            let grayscale_clicked = ui.button("Grayscale").clicked();
+            if grayscale_clicked {
+                model.state.adjust = Some(adjust::Adjustment::Grayscale);
+            }
+        });
+        ui.horizontal(|ui| {
+            let brighten_clicked = ui.button("Brighten").clicked();
+            if brighten_clicked {
+                model.state.adjust = Some(adjust::Adjustment::Brightness(
+                    model.settings.brightness_step,
+                ));
+            }
+
+            let darken_clicked = ui.button("Darken").clicked();
+            if darken_clicked {
+                model.state.adjust = Some(adjust::Adjustment::Brightness(
+                    -model.settings.brightness_step,
+                ));
+            }
+            ui.add(egui::DragValue::new(&mut model.settings.brightness_step).clamp_range(1..=255));
+        });
+        ui.horizontal(|ui| {
+            let posterize_clicked = ui.button("Posterize").clicked();
+            if posterize_clicked {
+                model.state.adjust = Some(adjust::Adjustment::Posterize(
+                    model.settings.posterize_levels,
+                ));
+            }
+            ui.add(
+                egui::DragValue::new(&mut model.settings.posterize_levels)
+                    .clamp_range(2..=16)
+                    .suffix(" levels"),
+            );
+        });
+
+        ui.horizontal(|ui| {
+            let flip_horizontal_clicked = ui.button("Flip Horizontal").clicked();
+            if flip_horizontal_clicked {
+                model.state.flip = Some(canvas::Flip::Horizontal);
+            }
+
+            let flip_vertical_clicked = ui.button("Flip Vertical").clicked();
+            if flip_vertical_clicked {
+                model.state.flip = Some(canvas::Flip::Vertical);
+            }
+        });
+
+        let rotate_clicked = ui
+            .add_enabled(
+                !model.state.confirm_rotate,
+                egui::Button::new("Rotate by Angle"),
+            )
+            .on_hover_text("Turns every layer about the center of the canvas")
+            .clicked();
+        if rotate_clicked {
+            model.state.confirm_rotate = true;
+        }
+
+        ui.label("File Name");
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut model.state.document_mut().file_name);
+            let browse_clicked = ui
+                .add_enabled(model.state.dialog.is_none(), egui::Button::new("Browse"))
+                .on_hover_text("Opens projects and imports PNGs")
+                .clicked();
+            if browse_clicked {
+                model.state.dialog = Some(dialog::Dialog::pick_file());
+            }
+        });
+
+        let save_project_clicked = ui.button("Save Project").clicked();
+        if save_project_clicked {
+            model.state.should_save_project = true;
+        }
+
+        let open_project_clicked = ui.button("Open Project").clicked();
+        if open_project_clicked {
+            model.state.should_open_project = true;
+        }
+
+        if !model.config.recent.is_empty() {
+            ui.label("Recent Projects");
+            for path in &model.config.recent {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                let recent_clicked = ui
+                    .button(name)
+                    .on_hover_text(path.display().to_string())
+                    .clicked();
+                if recent_clicked {
+                    model.state.document_mut().file_name =
+                        path.with_extension("").display().to_string();
+                    model.state.open_path = Some(path.clone());
+                    model.state.should_open_project = true;
+                }
+            }
+        }
+
+        let import_image_clicked = ui.button("Import PNG").clicked();
+        if import_image_clicked {
+            model.state.should_import_image = true;
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Resampling");
+            for (resample, name) in [
+                (import::Resample::Nearest, "Nearest"),
+                (import::Resample::Area, "Area"),
+            ] {
+                let resample_clicked = ui
+                    .add_enabled(model.settings.resample != resample, egui::Button::new(name))
+                    .on_hover_text(match resample {
+                        import::Resample::Nearest => "Keeps pixel art crisp",
+                        import::Resample::Area => "Keeps more detail when shrinking photos",
+                    })
+                    .clicked();
+                if resample_clicked {
+                    model.settings.resample = resample;
+                }
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Aspect");
+            for (aspect, name) in [
+                (import::Aspect::Stretch, "Stretch"),
+                (import::Aspect::Fit, "Fit"),
+                (import::Aspect::Fill, "Fill"),
+            ] {
+                let aspect_clicked = ui
+                    .add_enabled(model.settings.aspect != aspect, egui::Button::new(name))
+                    .on_hover_text(match aspect {
+                        import::Aspect::Stretch => "Squashes the whole image onto the grid",
+                        import::Aspect::Fit => "Shows the whole image with background around it",
+                        import::Aspect::Fill => "Covers the grid, cropping the longer sides",
+                    })
+                    .clicked();
+                if aspect_clicked {
+                    model.settings.aspect = aspect;
+                }
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut model.settings.extract_swatches, "Extract Swatches")
+                .on_hover_text("Picks the image's main colors when importing");
+            ui.add(egui::DragValue::new(&mut model.settings.swatch_count).clamp_range(1..=32));
+        });
+
+        ui.horizontal(|ui| {
+            let export_png_clicked = ui.button("Export PNG").clicked();
+            if export_png_clicked {
+                model.state.should_export_png = true;
+            }
+
+            let copy_image_clicked = ui
+                .button("Copy Image")
+                .on_hover_text("Copies the canvas with the PNG options below")
+                .clicked();
+            if copy_image_clicked {
+                model.state.should_copy_image = true;
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Padding");
+            ui.add(egui::DragValue::new(&mut model.settings.export_padding).clamp_range(0..=64));
+            ui.checkbox(&mut model.settings.padding_transparent, "Transparent");
+            if !model.settings.padding_transparent {
+                let padding_color_changed = ui
+                    .color_edit_button_srgb(&mut model.settings.padding_color_buf)
+                    .changed();
+                if padding_color_changed {
+                    model.settings.padding_color = rgb8(
+                        model.settings.padding_color_buf[0],
+                        model.settings.padding_color_buf[1],
+                        model.settings.padding_color_buf[2],
+                    );
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Scale");
+            ui.add(
+                egui::DragValue::new(&mut model.settings.export_scale)
+                    .clamp_range(1..=32)
+                    .suffix("x"),
+            )
+            .on_hover_text("Cell gaps are only exported at scales above 1x");
+        });
+        ui.checkbox(&mut model.settings.smooth_export, "Smooth Edges")
+            .on_hover_text("Lightly blurs exported PNGs, leaving the canvas as is");
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut model.settings.normal_map, "Normal Map")
+                .on_hover_text("Also exports a flat #8080ff normal map as _normal.png");
+            ui.checkbox(&mut model.settings.depth_map, "Depth Map")
+                .on_hover_text("Also exports the canvas's brightness as _depth.png");
+        });
+
+        let export_svg_clicked = ui.button("Export SVG").clicked();
+        if export_svg_clicked {
+            model.state.should_export_svg = true;
+        }
+
+        ui.horizontal(|ui| {
+            let export_ascii_clicked = ui.button("Export ASCII").clicked();
+            if export_ascii_clicked {
+                model.state.should_export_ascii = true;
+            }
+            ui.checkbox(&mut model.settings.ascii_colors, "ANSI Colors")
+                .on_hover_text("Colors the text for terminals that support it");
+        });
+
+        // The next canvas is the keyframe to tween towards
+        let next = model.state.documents.get(model.state.active + 1);
+        let current = model.state.document();
+        let can_tween =
+            next.is_some_and(|next| (next.width, next.height) == (current.width, current.height));
+        ui.horizontal(|ui| {
+            let tween_clicked = ui
+                .add_enabled(can_tween, egui::Button::new("Generate In-betweens"))
+                .on_hover_text("Blends this canvas into the next tab")
+                .clicked();
+            if tween_clicked {
+                model.state.should_tween = true;
+            }
+
+            ui.add(egui::DragValue::new(&mut model.settings.tween_count).clamp_range(1..=16));
+            ui.checkbox(&mut model.settings.tween_discrete, "Discrete");
+        });
+
+        let export_palette_clicked = ui.button("Export Palette").clicked();
+        if export_palette_clicked {
+            model.state.should_export_palette = true;
+        }
+
+        ui.group(|ui| {
+            let gpl_clicked = ui
+                .add_enabled(
+                    model.settings.palette_format != PaletteFormat::Gpl,
+                    egui::Button::new("GPL"),
+                )
+                .clicked();
+            if gpl_clicked {
+                model.settings.palette_format = PaletteFormat::Gpl;
+            }
+
+            let hex_clicked = ui
+                .add_enabled(
+                    model.settings.palette_format != PaletteFormat::Hex,
+                    egui::Button::new("HEX"),
+                )
+                .clicked();
+            if hex_clicked {
+                model.settings.palette_format = PaletteFormat::Hex;
+            }
+        });
+        ui.checkbox(
+            &mut model.settings.palette_skip_background,
+            "Skip Background",
+        );
+
+        if let Some(cells) = model.state.stroke_cells {
+            ui.label(format!("Stroke changed {cells} cells"));
+        }
+        if let Some(status) = &model.state.status {
+            ui.label(status);
+        }
+    });
+
+    egui::Window::new("History")
+        .default_open(false)
+        .show(&ctx, |ui| {
+            // Picking an entry returns the canvas to just after it
+            let history = &model.state.document().history;
+            let position = history.position();
+            let mut jump_to = None;
+            egui::ScrollArea::vertical()
+                .max_height(200.0)
+                .show(ui, |ui| {
+                    let start_clicked = ui.selectable_label(position == 0, "Start").clicked();
+                    if start_clicked {
+                        jump_to = Some(0);
+                    }
+
+                    for (i, label) in history.labels().enumerate() {
+                        let entry_clicked = ui.selectable_label(position == i + 1, label).clicked();
+                        if entry_clicked {
+                            jump_to = Some(i + 1);
+                        }
+                    }
+                });
+            ui.label(format!(
+                "Using about {:.1} KiB",
+                history.bytes() as f32 / 1024.0
+            ));
+
+            if jump_to.is_some() {
+                model.state.jump_history = jump_to;
+            }
+        });
+
+    egui::Window::new("Color Usage")
+        .default_open(false)
+        .show(&ctx, |ui| {
+            let stale = model
+                .state
+                .color_counts_at
+                .is_none_or(|at| at.elapsed() >= COLOR_COUNT_INTERVAL);
+            if stale {
+                let background = model.settings.background_color;
+                let pixels = model.state.document().flatten(background);
+                model.state.color_counts = palette::count_colors(
+                    pixels.iter().flatten().map(|pixel| &pixel.color),
+                    Some(background),
+                );
+                model.state.color_counts_at = Some(Instant::now());
+            }
+
+            // Bars are scaled against the most used color
+            let most = model
+                .state
+                .color_counts
+                .first()
+                .map_or(1, |&(_, count)| count);
+            egui::ScrollArea::vertical()
+                .max_height(200.0)
+                .show(ui, |ui| {
+                    for &(color, count) in &model.state.color_counts {
+                        ui.horizontal(|ui| {
+                            let width = 120.0 * count as f32 / most as f32;
+                            ui.add(
+                                egui::Button::new("")
+                                    .fill(egui::Color32::from_rgb(
+                                        color.red,
+                                        color.green,
+                                        color.blue,
+                                    ))
+                                    .min_size(Vec2::new(width.max(4.0), 12.0)),
+                            )
+                            .on_hover_text(format!("#{}", palette::to_hex(color)));
+                            ui.label(count.to_string());
+                        });
+                    }
+                });
+        });
+
+    egui::Window::new("Layers").show(&ctx, |ui| {
+        // List the top layer first, like most editors do
+        let active = model.state.document().active_layer;
+        let mut switch_layer = None;
+        let mut changed = false;
+        for (i, layer) in model
+            .state
+            .document_mut()
+            .layers
+            .iter_mut()
+            .enumerate()
+            .rev()
+        {
+            ui.horizontal(|ui| {
+                changed |= ui.checkbox(&mut layer.visible, "").changed();
+                let layer_clicked = ui.selectable_label(i == active, &layer.name).clicked();
+                if layer_clicked && i != active {
+                    switch_layer = Some(i);
+                }
+            });
         }
+        if switch_layer.is_some() {
+            model.state.switch_layer = switch_layer;
+        }
+
+        // Blend modes and opacity are picked for the active layer
+        let layer = &mut model.state.document_mut().layers[active];
+        ui.horizontal(|ui| {
+            let mut percent = (layer.opacity * 100.0).round() as u8;
+            ui.label("Opacity");
+            let opacity_changed = ui
+                .add(egui::Slider::new(&mut percent, 0..=100).suffix("%"))
+                .changed();
+            if opacity_changed {
+                layer.opacity = percent as f32 / 100.0;
+                changed = true;
+            }
+        });
+
+        ui.group(|ui| {
+            for (blend, name) in [
+                (Blend::Normal, "Normal"),
+                (Blend::Multiply, "Multiply"),
+                (Blend::Additive, "Additive"),
+                (Blend::Screen, "Screen"),
+            ] {
+                let blend_clicked = ui
+                    .add_enabled(layer.blend != blend, egui::Button::new(name))
+                    .clicked();
+                if blend_clicked {
+                    layer.blend = blend;
+                    changed = true;
+                }
+            }
+        });
+        if changed {
+            model.state.document_mut().dirty = true;
+        }
+
+        let add_layer_clicked = ui.button("Add Layer").clicked();
+        if add_layer_clicked {
+            model.state.should_add_layer = true;
+        }
+
+        let document = model.state.document();
+        let can_merge = active > 0 && document.layers[active].visible;
+        let merge_down_clicked = ui
+            .add_enabled(can_merge, egui::Button::new("Merge Down"))
+            .clicked();
+        if merge_down_clicked {
+            model.state.should_merge_down = true;
+        }
+
+        let export_layers_clicked = ui.button("Export Layers").clicked();
+        if export_layers_clicked {
+            model.state.should_export_layers = true;
+        }
+        ui.checkbox(
+            &mut model.settings.export_hidden_layers,
+            "Include Hidden Layers",
+        );
     });
 
     egui::Window::new("Settings").show(&ctx, |ui| {
+        ui.label("Presets")
+            .on_hover_text("The brush, colors, swatches, grid size and symmetry");
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut model.state.preset_name);
+            let save_preset_clicked = ui
+                .add_enabled(
+                    !model.state.preset_name.trim().is_empty(),
+                    egui::Button::new("Save"),
+                )
+                .clicked();
+            if save_preset_clicked {
+                model.state.should_save_preset = true;
+            }
+        });
+        egui::ComboBox::from_id_source("presets")
+            .selected_text("Load Preset")
+            .show_ui(ui, |ui| {
+                for name in &model.state.presets {
+                    let preset_clicked = ui.selectable_label(false, name).clicked();
+                    if preset_clicked {
+                        model.state.load_preset = Some(name.clone());
+                    }
+                }
+            })
+            .response
+            .on_hover_text("Loading a different grid size resets the canvas");
+
         ui.label("Primary Color");
         let primary_color_changed = ui
             .color_edit_button_srgb(&mut model.settings.primary_color_buf)
@@ -285,53 +2698,560 @@ fn update(app: &App, model: &mut Model, update: Update) {
             );
         }
 
-        ui.label("Grid Size");
-        let grid_resized = ui
-            .add(egui::Slider::new(&mut model.settings.grid_size, 1..=64))
+        let paste_palette_clicked = ui
+            .button("Paste Palette")
+            .on_hover_text("Sets the swatches to a list of hex colors from the clipboard")
+            .clicked();
+        if paste_palette_clicked {
+            model.state.should_paste_palette = true;
+        }
+
+        if !model.settings.swatches.is_empty() {
+            ui.label("Swatches").on_hover_text(
+                "Left click for the primary color, right click for the secondary\n\
+                     Keys 1 to 9 pick the first nine as the primary color",
+            );
+            ui.horizontal_wrapped(|ui| {
+                for color in &model.settings.swatches {
+                    let response = ui.add(
+                        egui::Button::new("")
+                            .fill(egui::Color32::from_rgb(color.red, color.green, color.blue))
+                            .min_size(Vec2::splat(16.0)),
+                    );
+                    let response = response.on_hover_text(format!(
+                        "{} (#{})",
+                        palette::name(*color),
+                        palette::to_hex(*color)
+                    ));
+                    if response.clicked() {
+                        model.settings.primary_color = *color;
+                        model.settings.primary_color_buf = [color.red, color.green, color.blue];
+                    } else if response.secondary_clicked() {
+                        model.settings.secondary_color = *color;
+                        model.settings.secondary_color_buf = [color.red, color.green, color.blue];
+                    }
+                }
+            });
+        }
+
+        ui.horizontal(|ui| {
+            let primary = model.settings.primary_color;
+            let lock_clicked = ui
+                .add_enabled(
+                    !model.settings.is_locked(primary),
+                    egui::Button::new("Lock Primary"),
+                )
+                .on_hover_text("Keeps cells of the primary color from being painted over")
+                .clicked();
+            if lock_clicked {
+                model.settings.locked_colors.push(primary);
+            }
+        });
+        if !model.settings.locked_colors.is_empty() {
+            ui.label("Locked Colors")
+                .on_hover_text("Click a color to unlock it");
+            ui.horizontal_wrapped(|ui| {
+                let mut unlock = None;
+                for (i, color) in model.settings.locked_colors.iter().enumerate() {
+                    let unlock_clicked = ui
+                        .add(
+                            egui::Button::new("")
+                                .fill(egui::Color32::from_rgb(color.red, color.green, color.blue))
+                                .min_size(Vec2::splat(16.0)),
+                        )
+                        .clicked();
+                    if unlock_clicked {
+                        unlock = Some(i);
+                    }
+                }
+                if let Some(i) = unlock {
+                    model.settings.locked_colors.remove(i);
+                }
+            });
+        }
+
+        ui.label("Background Color")
+            .on_hover_text("Left out of SVG exports as transparent");
+        let background_color_changed = ui
+            .color_edit_button_srgb(&mut model.settings.background_color_buf)
+            .changed();
+        if background_color_changed {
+            model.settings.background_color = rgb8(
+                model.settings.background_color_buf[0],
+                model.settings.background_color_buf[1],
+                model.settings.background_color_buf[2],
+            );
+        }
+
+        ui.label("Canvas Fill")
+            .on_hover_text("Fills new canvases and resets");
+        let fill_color_changed = ui
+            .color_edit_button_srgb(&mut model.settings.fill_color_buf)
+            .changed();
+        if fill_color_changed {
+            model.settings.fill_color = rgb8(
+                model.settings.fill_color_buf[0],
+                model.settings.fill_color_buf[1],
+                model.settings.fill_color_buf[2],
+            );
+            model.config.fill_color = Some(model.settings.fill_color_buf);
+            if let Err(e) = model.config.save() {
+                eprintln!("Failed to save config: {e}");
+            }
+        }
+
+        let dimension = |ui: &mut egui::Ui, label: &str, size: &mut usize| {
+            ui.label(label);
+            ui.horizontal(|ui| {
+                let slid = ui
+                    .add(egui::Slider::new(size, 1..=64).show_value(false))
+                    .changed();
+                // Typed sizes don't apply until the field loses focus
+                let typed = ui
+                    .add(
+                        egui::DragValue::new(size)
+                            .clamp_range(1..=64)
+                            .update_while_editing(false),
+                    )
+                    .changed();
+                slid || typed
+            })
+            .inner
+        };
+        let (old_width, old_height) = (model.state.document().width, model.state.document().height);
+        let (mut width, mut height) = (old_width, old_height);
+        let width_changed = dimension(ui, "Grid Width", &mut width);
+        let height_changed = dimension(ui, "Grid Height", &mut height);
+        ui.checkbox(&mut model.settings.lock_aspect, "Lock Aspect");
+        if model.settings.lock_aspect {
+            // Scale the other side by the same amount, rounding to the nearest cell
+            if width_changed {
+                height = (width as f32 * old_height as f32 / old_width as f32).round() as usize;
+            } else if height_changed {
+                width = (height as f32 * old_width as f32 / old_height as f32).round() as usize;
+            }
+        }
+        let (width, height) = (width.clamp(1, 64), height.clamp(1, 64));
+        if (width, height) != (old_width, old_height) {
+            let document = model.state.document_mut();
+            (document.width, document.height) = (width, height);
+            model.state.should_reset = true;
+        }
+
+        // Every layer holds a full grid of cells
+        let document = model.state.document();
+        let bytes =
+            document.width * document.height * std::mem::size_of::<Pixel>() * document.layers.len();
+        ui.label(format!(
+            "{} x {} cells, about {:.1} KiB",
+            document.width,
+            document.height,
+            bytes as f32 / 1024.0,
+        ));
+
+        ui.label("Brush Size");
+        ui.add(egui::Slider::new(
+            &mut model.settings.brush_size,
+            1..=model.state.document().longest_side(),
+        ));
+
+        ui.label("Eraser Size");
+        ui.add(egui::Slider::new(
+            &mut model.settings.eraser_size,
+            1..=model.state.document().longest_side(),
+        ));
+
+        // One-shot tools don't have a footprint to count
+        let footprint = model.settings.brush.is_continuous().then(|| {
+            brush_cells(
+                &model.settings.brush,
+                model.settings.brush_size,
+                model.settings.circle_bias,
+                0,
+                0,
+            )
+            .len()
+        });
+        if let Some(cells) = footprint {
+            ui.label(format!("Paints {cells} cells"));
+        }
+
+        ui.label("Brush Type");
+        ui.group(|ui| {
+            let square_clicked = ui
+                .add_enabled(
+                    !matches!(model.settings.brush, Brush::Square),
+                    egui::Button::new("Square"),
+                )
+                .clicked();
+            if square_clicked {
+                model.settings.brush = Brush::Square;
+            }
+
+            let circle_clicked = ui
+                .add_enabled(
+                    !matches!(model.settings.brush, Brush::Circle),
+                    egui::Button::new("Circle"),
+                )
+                .clicked();
+            if circle_clicked {
+                model.settings.brush = Brush::Circle;
+            }
+
+            let fill_clicked = ui
+                .add_enabled(
+                    !matches!(model.settings.brush, Brush::Fill),
+                    egui::Button::new("Fill"),
+                )
+                .clicked();
+            if fill_clicked {
+                model.settings.brush = Brush::Fill;
+            }
+
+            let line_clicked = ui
+                .add_enabled(
+                    !matches!(model.settings.brush, Brush::Line),
+                    egui::Button::new("Line"),
+                )
+                .clicked();
+            if line_clicked {
+                model.settings.brush = Brush::Line;
+            }
+
+            let lasso_clicked = ui
+                .add_enabled(
+                    !matches!(model.settings.brush, Brush::Lasso),
+                    egui::Button::new("Lasso"),
+                )
+                .clicked();
+            if lasso_clicked {
+                model.settings.brush = Brush::Lasso;
+            }
+
+            let wand_clicked = ui
+                .add_enabled(
+                    !matches!(model.settings.brush, Brush::Wand),
+                    egui::Button::new("Magic Wand"),
+                )
+                .clicked();
+            if wand_clicked {
+                model.settings.brush = Brush::Wand;
+            }
+
+            let eyedropper_clicked = ui
+                .add_enabled(
+                    !matches!(model.settings.brush, Brush::Eyedropper),
+                    egui::Button::new("Eyedropper"),
+                )
+                .clicked();
+            if eyedropper_clicked {
+                model.settings.brush = Brush::Eyedropper;
+            }
+        });
+
+        let reset_tool_clicked = ui
+            .button("Reset Tool")
+            .on_hover_text("Goes back to the default brush and sizes")
+            .clicked();
+        if reset_tool_clicked {
+            model.settings.reset_tool();
+        }
+
+        if let Brush::Wand = model.settings.brush {
+            ui.label("Wand Tolerance");
+            ui.add(egui::Slider::new(
+                &mut model.settings.wand_tolerance,
+                0..=255,
+            ));
+        }
+
+        if let Brush::Circle = model.settings.brush {
+            ui.label("Circle Edge");
+            ui.add(egui::Slider::new(
+                &mut model.settings.circle_bias,
+                -1.0..=1.0,
+            ))
+            .on_hover_text("Higher values fill in more of the cells around the edge");
+        }
+
+        if let Brush::Eyedropper = model.settings.brush {
+            ui.checkbox(&mut model.settings.eyedropper_average, "Average Over Brush")
+                .on_hover_text("Picks the mean color of the cells under the brush");
+        }
+
+        ui.label("Symmetry Segments");
+        ui.add(egui::Slider::new(
+            &mut model.settings.symmetry_segments,
+            1..=12,
+        ))
+        .on_hover_text("Repeats brush strokes around the center of the grid");
+
+        if let Brush::Line = model.settings.brush {
+            ui.horizontal(|ui| {
+                ui.label("Snap Every");
+                ui.add(
+                    egui::DragValue::new(&mut model.settings.snap_step)
+                        .clamp_range(1..=64)
+                        .suffix(" cells"),
+                )
+                .on_hover_text("Line ends land on multiples of this many cells");
+            });
+
+            ui.checkbox(&mut model.settings.line_gradient, "Gradient Line")
+                .on_hover_text("Blend from the primary to the secondary color along the line");
+
+            ui.checkbox(&mut model.settings.two_click_line, "Two Click Line")
+                .on_hover_text("Click once for each end of the line, Escape cancels");
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Undo Memory");
+            ui.add(
+                egui::DragValue::new(&mut model.settings.undo_budget)
+                    .clamp_range(1..=1024)
+                    .suffix(" MiB"),
+            )
+            .on_hover_text("The oldest operations are forgotten once a layer's history uses more");
+        });
+
+        ui.label("Reset Key");
+        ui.group(|ui| {
+            for (key, name) in [
+                (ResetKey::Immediate, "R"),
+                (ResetKey::Ctrl, "Ctrl+R"),
+                (ResetKey::Confirm, "R, then Confirm"),
+            ] {
+                let key_clicked = ui
+                    .add_enabled(model.settings.reset_key != key, egui::Button::new(name))
+                    .clicked();
+                if key_clicked {
+                    model.settings.reset_key = key;
+                }
+            }
+        });
+
+        ui.label("Middle Click");
+        ui.group(|ui| {
+            for (action, name) in [
+                (MiddleClick::Paint, "Paints Secondary"),
+                (MiddleClick::Pan, "Pans"),
+            ] {
+                let action_clicked = ui
+                    .add_enabled(
+                        model.settings.middle_click != action,
+                        egui::Button::new(name),
+                    )
+                    .clicked();
+                if action_clicked {
+                    model.settings.middle_click = action;
+                }
+            }
+        });
+
+        ui.checkbox(&mut model.settings.paint_behind, "Paint Behind")
+            .on_hover_text("Only paint over cells of the background color");
+
+        ui.checkbox(&mut model.settings.toggle_paint, "Toggle Cells")
+            .on_hover_text("Left strokes that start on the primary color clear it instead");
+
+        ui.label("Hardness");
+        ui.add(egui::Slider::new(&mut model.settings.hardness, 0.0..=1.0))
+            .on_hover_text("Lower values fade the brush out towards its edge");
+
+        ui.label("Scatter");
+        ui.add(egui::Slider::new(&mut model.settings.scatter, 0.0..=16.0).suffix(" cells"))
+            .on_hover_text("Randomly moves each stamp for organic textures");
+
+        ui.label("Stabilizer");
+        ui.add(egui::Slider::new(
+            &mut model.settings.stabilizer,
+            0.0..=0.95,
+        ))
+        .on_hover_text("Smooths out shaky freehand strokes");
+
+        ui.checkbox(&mut model.settings.wrap_strokes, "Wrap Around Edges")
+            .on_hover_text(
+                "Brushes crossing an edge continue on the opposite one, for seamless tiles",
+            );
+
+        ui.checkbox(&mut model.settings.paint_on_move, "Paint Only On Move")
+            .on_hover_text(
+                "Skip painting while the mouse is held still, \
+                 turn off to keep dithering and rainbows changing",
+            );
+
+        ui.checkbox(&mut model.settings.dither, "Dither")
+            .on_hover_text("Randomly mix in the secondary color while drawing");
+        if model.settings.dither {
+            ui.label("Dither Mix");
+            ui.add(egui::Slider::new(&mut model.settings.dither_mix, 0.0..=1.0).text("primary"));
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Random Seed");
+            let mut seed_changed = ui
+                .add(egui::DragValue::new(&mut model.settings.seed))
+                .on_hover_text("Random tools repeat the same pattern for the same seed")
+                .changed();
+
+            let reseed_clicked = ui.button("Reseed").clicked();
+            if reseed_clicked {
+                model.settings.seed = model.rng.gen();
+                seed_changed = true;
+            }
+
+            if seed_changed {
+                model.rng = StdRng::seed_from_u64(model.settings.seed);
+            }
+        });
+
+        ui.checkbox(&mut model.settings.rainbow, "Rainbow")
+            .on_hover_text("Cycle through every hue while drawing");
+        if model.settings.rainbow {
+            ui.label("Rainbow Speed");
+            ui.add(
+                egui::Slider::new(&mut model.settings.rainbow_speed, 10.0..=720.0).suffix("°/s"),
+            );
+        }
+
+        ui.checkbox(&mut model.settings.pressure_size, "Pressure Sets Size")
+            .on_hover_text("Scales the brush by stylus pressure on devices that report it");
+
+        ui.checkbox(&mut model.settings.brush_preview, "Brush Preview")
+            .on_hover_text("Shows the cells under the mouse that the brush will paint");
+
+        if model.settings.brush_preview {
+            ui.label("Preview Delay");
+            ui.add(egui::Slider::new(&mut model.settings.preview_delay, 0.0..=1.0).suffix(" s"))
+                .on_hover_text("Waits for the mouse to stop before showing the preview");
+        }
+
+        ui.checkbox(&mut model.settings.keyboard_cursor, "Keyboard Cursor")
+            .on_hover_text("Move with the arrow keys, paint with space and erase with backspace");
+
+        let crisp_changed = ui
+            .checkbox(&mut model.settings.crisp, "Crisp Pixels")
+            .on_hover_text("Align cells to whole screen pixels")
             .changed();
-        if grid_resized {
-            model.state.should_reset = true;
+        if crisp_changed {
+            model.state.should_calc_positions = true;
         }
 
-        ui.label("Brush Size");
-        ui.add(egui::Slider::new(
-            &mut model.settings.brush_size,
-            1..=model.settings.grid_size,
-        ));
-
-        ui.label("Brush Type");
+        ui.label("Coordinate Origin").on_hover_text(
+            "Center: (0, 0) is the middle cell and y increases upwards\n\
+             Top Left: (0, 0) is the top left cell and y increases downwards, \
+             matching the pixels of exported images",
+        );
         ui.group(|ui| {
-            let square_clicked = ui
-                .add_enabled(
-                    if let Brush::Square = model.settings.brush {
-                        false
-                    } else {
-                        true
-                    },
-                    egui::Button::new("Square"),
-                )
-                .clicked();
-            if square_clicked {
-                model.settings.brush = Brush::Square;
+            for (origin, name) in [(Origin::Center, "Center"), (Origin::TopLeft, "Top Left")] {
+                let origin_clicked = ui
+                    .add_enabled(model.settings.origin != origin, egui::Button::new(name))
+                    .clicked();
+                if origin_clicked {
+                    model.settings.origin = origin;
+                }
             }
+        });
 
-            let circle_clicked = ui
-                .add_enabled(
-                    if let Brush::Circle = model.settings.brush {
-                        false
-                    } else {
-                        true
-                    },
-                    egui::Button::new("Circle"),
-                )
+        ui.horizontal(|ui| {
+            ui.label("Tile Offset").on_hover_text(
+                "Where the canvas sits in a larger tile, in cells from its top left. \
+                 Shifts the coordinates shown and pads PNG exports to match",
+            );
+            for offset in &mut model.settings.tile_offset {
+                ui.add(egui::DragValue::new(offset).clamp_range(0..=1024));
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label(format!("Zoom: {:.0}%", model.state.document().zoom * 100.0));
+            let fit_clicked = ui
+                .button("Fit to Window")
+                .on_hover_text("Ctrl+Scroll zooms and Ctrl+Arrows pan the canvas")
                 .clicked();
-            if circle_clicked {
-                model.settings.brush = Brush::Circle;
+            if fit_clicked {
+                model.state.document_mut().zoom = 1.0;
+                model.state.document_mut().pan = Point2::ZERO;
+                model.state.should_calc_positions = true;
+            }
+        });
+
+        ui.checkbox(&mut model.settings.rulers, "Rulers");
+
+        ui.checkbox(&mut model.settings.letterbox, "Letterbox");
+
+        ui.label("Cell Gap");
+        ui.add(egui::Slider::new(&mut model.settings.cell_gap, 0.0..=0.5))
+            .on_hover_text("Leaves space between cells for a mosaic look");
+
+        ui.label("Backdrop")
+            .on_hover_text("What shows through cells of the background color");
+        ui.group(|ui| {
+            for (backdrop, name) in [(Backdrop::Solid, "Solid"), (Backdrop::Checker, "Checker")] {
+                let backdrop_clicked = ui
+                    .add_enabled(model.settings.backdrop != backdrop, egui::Button::new(name))
+                    .clicked();
+                if backdrop_clicked {
+                    model.settings.backdrop = backdrop;
+                }
+            }
+        });
+
+        ui.checkbox(&mut model.settings.guides, "Center Guides");
+
+        ui.checkbox(&mut model.settings.cell_outlines, "Cell Outlines")
+            .on_hover_text("Outlines each rectangle the canvas is drawn with, for debugging");
+
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut model.settings.show_orphans, "Stray Pixels")
+                .on_hover_text("Marks cells with no neighbors of the same color");
+            if model.settings.show_orphans {
+                let background = model.settings.background_color;
+                let pixels = model.state.document().flatten(background);
+                ui.label(format!(
+                    "{} found",
+                    canvas::orphans(&pixels, background).len()
+                ));
             }
         });
 
         ui.checkbox(&mut model.settings.display_fps, "Display FPS");
 
+        if model.settings.display_fps {
+            ui.label("FPS Corner");
+            ui.group(|ui| {
+                for (corner, name) in [
+                    (Corner::TopLeft, "Top Left"),
+                    (Corner::TopRight, "Top Right"),
+                    (Corner::BottomLeft, "Bottom Left"),
+                    (Corner::BottomRight, "Bottom Right"),
+                ] {
+                    let corner_clicked = ui
+                        .add_enabled(model.settings.fps_corner != corner, egui::Button::new(name))
+                        .clicked();
+                    if corner_clicked {
+                        model.settings.fps_corner = corner;
+                    }
+                }
+            });
+        }
+
+        ui.label("Frame Rate");
+        ui.group(|ui| {
+            for (cap, name) in [
+                (FrameCap::Thirty, "30"),
+                (FrameCap::Sixty, "60"),
+                (FrameCap::Uncapped, "Uncapped"),
+            ] {
+                let cap_clicked = ui
+                    .add_enabled(model.settings.frame_cap != cap, egui::Button::new(name))
+                    .clicked();
+                if cap_clicked {
+                    model.settings.frame_cap = cap;
+                }
+            }
+        });
+
         ui.label("Theme");
         ui.group(|ui| {
             let light_clicked = ui
@@ -348,87 +3268,425 @@ fn update(app: &App, model: &mut Model, update: Update) {
                 model.settings.dark_mode = true;
             }
         });
+
+        let high_contrast_changed = ui
+            .checkbox(&mut model.settings.high_contrast, "High Contrast")
+            .on_hover_text("Larger text and controls with stronger outlines")
+            .changed();
+        if high_contrast_changed {
+            model.config.high_contrast = model.settings.high_contrast;
+            if let Err(e) = model.config.save() {
+                eprintln!("Failed to save config: {e}");
+            }
+        }
     });
+
+    // Nannou's rate loop mode currently behaves the same as
+    // refresh sync, so we have to limit the frame rate ourselves
+    if let Some(interval) = model.settings.frame_cap.interval() {
+        let elapsed = model.state.last_frame.elapsed();
+        if elapsed < interval {
+            std::thread::sleep(interval - elapsed);
+        }
+    }
+    model.state.last_frame = Instant::now();
 }
 
 fn view(app: &App, model: &Model, frame: Frame) {
-    let win = app.window_rect();
-    let draw = app.draw();
-    let diff = win.w().min(win.h()) / model.settings.grid_size as f32;
+    let diff = cell_size(app, &model.settings, model.state.document());
+    let draw = if model.settings.crisp {
+        // The window's center falls between device pixels when
+        // its size is odd, so shift everything onto the pixel grid
+        let window = app.main_window();
+        let (w, h) = window.inner_size_pixels();
+        let scale = window.scale_factor();
+        app.draw()
+            .x_y((w % 2) as f32 * 0.5 / scale, (h % 2) as f32 * 0.5 / scale)
+    } else {
+        app.draw()
+    };
 
-    draw.background().color(LIGHTGRAY);
+    // Everything on the canvas is drawn relative to its center
+    let offset = model.state.document().pan * diff;
+    let canvas = draw.xy(offset);
+
+    if model.settings.letterbox {
+        // Darken the space around the canvas so the
+        // drawable area stands out on wide windows
+        let document = model.state.document();
+        let (width, height) = (document.width as f32 * diff, document.height as f32 * diff);
+        draw.background().color(DIMGRAY);
+        canvas
+            .rect()
+            .x_y(0.0, 0.0)
+            .w_h(width, height)
+            .color(LIGHTGRAY);
+    } else {
+        draw.background().color(LIGHTGRAY);
+    }
 
     // Draw grid
-    for (x, row) in model.state.pixels.iter().enumerate() {
-        let mut amt = 0.0;
-        for (y, pixel) in row.iter().enumerate() {
-            if y < amt as usize {
+    let pixels = model
+        .state
+        .document()
+        .flatten(model.settings.background_color);
+    let checker = model.settings.backdrop == Backdrop::Checker;
+    let gap = model.settings.cell_gap * diff;
+    let outline_weight = 1.0 / app.main_window().scale_factor();
+    let outline = |x: f32, y: f32, w: f32, h: f32| {
+        if model.settings.cell_outlines {
+            canvas
+                .rect()
+                .x_y(x, y)
+                .w_h(w, h)
+                .no_fill()
+                .stroke(OUTLINE)
+                .stroke_weight(outline_weight);
+        }
+    };
+    for (x, row) in pixels.iter().enumerate() {
+        // Gaps have to show between every cell, so runs can't be merged
+        for (y, len) in color_runs(row, gap > 0.0) {
+            let pixel = &row[y];
+            let amt = len as f32;
+
+            // Empty cells show a checkerboard instead of their color
+            if checker && pixel.color == model.settings.background_color {
+                for (y, cell) in row.iter().enumerate().skip(y).take(len) {
+                    let tone = if (x + y) % 2 == 0 { WHITE } else { LIGHTGRAY };
+                    canvas
+                        .rect()
+                        .w_h(diff - gap, diff - gap)
+                        .x_y(cell.x, cell.y)
+                        .color(tone);
+                    outline(cell.x, cell.y, diff - gap, diff - gap);
+                }
                 continue;
             }
 
-            amt = 0.0;
-            for y_2 in y..model.state.pixels.len() {
-                if pixel.color != model.state.pixels[x][y_2].color {
-                    break;
+            let run_y = pixel.y + (diff * (amt - 1.0)) / 2.0;
+            canvas
+                .rect()
+                .w_h(diff - gap, diff * amt - gap)
+                .x_y(pixel.x, run_y)
+                .color(pixel.color);
+            outline(pixel.x, run_y, diff - gap, diff * amt - gap);
+        }
+    }
+
+    // Mark stray pixels, as they'll look once exported
+    if model.settings.show_orphans {
+        let document = model.state.document();
+        for (x, y) in canvas::orphans(&pixels, model.settings.background_color) {
+            canvas
+                .rect()
+                .xy(cell_center(
+                    x as i32,
+                    y as i32,
+                    document.width,
+                    document.height,
+                    diff,
+                ))
+                .w_h(diff, diff)
+                .no_fill()
+                .stroke(ORPHAN)
+                .stroke_weight((diff / 6.0).max(1.0));
+        }
+    }
+
+    // Draw pixels over mouse, unless the cursor is off in another
+    // window, the preview is turned off or it hasn't settled yet
+    let still = app
+        .duration
+        .since_start
+        .saturating_sub(model.state.mouse_moved_at);
+    let opacity = if model.settings.preview_delay > 0.0 {
+        let waited = still.as_secs_f32() - model.settings.preview_delay;
+        (waited / PREVIEW_FADE.as_secs_f32()).clamp(0.0, 1.0)
+    } else {
+        1.0
+    };
+    if model.state.focused && model.settings.brush_preview && opacity > 0.0 {
+        let overlay = Rgba8 {
+            alpha: (OVERLAY.alpha as f32 * opacity) as u8,
+            ..OVERLAY
+        };
+        let anchor = rgba8(
+            ANCHOR.red,
+            ANCHOR.green,
+            ANCHOR.blue,
+            (255.0 * opacity) as u8,
+        );
+        let mouse = canvas_mouse(app, model.state.document(), diff);
+        let mouse_pos = Point2::new(
+            ((mouse.x / diff).floor() + 0.5) * diff,
+            ((mouse.y / diff).floor() + 0.5) * diff,
+        );
+        let size = if model.state.erasing && !model.state.drawing && !model.state.drawing_secondary
+        {
+            model.settings.eraser_size
+        } else {
+            model.settings.brush_size
+        };
+
+        match model.settings.brush {
+            // Show exactly the cells a stroke would paint
+            Brush::Square | Brush::Circle => {
+                let document = model.state.document();
+                let (x, y) = mouse_cell(app, document, diff);
+                let cells = brush_cells(
+                    &model.settings.brush,
+                    size,
+                    model.settings.circle_bias,
+                    x,
+                    y,
+                );
+                for &(x, y) in &cells {
+                    canvas
+                        .rect()
+                        .color(overlay)
+                        .xy(cell_center(x, y, document.width, document.height, diff))
+                        .w_h(diff, diff);
                 }
 
-                amt += 1.0;
+                // Mark the cell the brush is anchored on, which even
+                // sized brushes otherwise give no hint of
+                if cells.len() > 1 {
+                    canvas
+                        .rect()
+                        .xy(cell_center(x, y, document.width, document.height, diff))
+                        .w_h(diff, diff)
+                        .no_fill()
+                        .stroke(anchor)
+                        .stroke_weight((diff / 10.0).max(1.0));
+                }
+            }
+            // Show where the line will be placed
+            Brush::Line => {
+                let line = match (model.state.stroke_start, model.state.last_cell) {
+                    (Some(start), Some(end)) => Some((start, end)),
+                    // A two click line follows the cursor until it is placed
+                    _ => model.state.line_start.map(|start| {
+                        let cell = snap_cell(
+                            mouse_cell(app, model.state.document(), diff),
+                            model.settings.snap_step,
+                        );
+                        if model.state.constrain_stroke {
+                            (start, constrain_line(start, cell))
+                        } else {
+                            (start, cell)
+                        }
+                    }),
+                };
+                if let Some((start, end)) = line {
+                    for (x, y) in calc_line_pixels(start, end) {
+                        canvas
+                            .rect()
+                            .color(overlay)
+                            .xy(cell_center(
+                                x,
+                                y,
+                                model.state.document().width,
+                                model.state.document().height,
+                                diff,
+                            ))
+                            .w_h(diff, diff);
+                    }
+                }
+            }
+            // Show the path traced so far
+            Brush::Lasso => {
+                let document = model.state.document();
+                let h = vec2(document.width as f32, document.height as f32) / 2.0;
+                canvas
+                    .polyline()
+                    .weight((diff / 8.0).max(1.0))
+                    .color(overlay)
+                    .points(model.state.lasso.iter().map(|point| (*point - h) * diff));
             }
+            // Show the cells that will be averaged
+            Brush::Eyedropper if model.settings.eyedropper_average => {
+                let document = model.state.document();
+                let (x, y) = mouse_cell(app, document, diff);
+                for (x, y) in eyedropper_cells(&model.settings, size, x, y) {
+                    canvas
+                        .rect()
+                        .color(overlay)
+                        .xy(cell_center(x, y, document.width, document.height, diff))
+                        .w_h(diff, diff);
+                }
+            }
+            // Show the cells the fill would change, if they
+            // have been worked out for this cell yet
+            Brush::Fill => {
+                let document = model.state.document();
+                if model.state.fill_preview_cell == Some(mouse_cell(app, document, diff)) {
+                    for &(x, y) in &model.state.fill_preview {
+                        canvas
+                            .rect()
+                            .color(overlay)
+                            .xy(cell_center(
+                                x as i32,
+                                y as i32,
+                                document.width,
+                                document.height,
+                                diff,
+                            ))
+                            .w_h(diff, diff);
+                    }
+                }
+            }
+            Brush::Wand | Brush::Eyedropper => (),
+        }
 
-            draw.rect()
-                .w_h(diff, diff * amt)
-                .x_y(pixel.x, pixel.y + (diff * (amt - 1.0)) / 2.0)
-                .color(pixel.color);
+        // One-shot tools get a crosshair so they
+        // aren't mistaken for a brush footprint
+        if !model.settings.brush.is_continuous() {
+            let weight = (diff / 8.0).max(1.0);
+            canvas
+                .line()
+                .start(mouse_pos - vec2(diff / 2.0, 0.0))
+                .end(mouse_pos + vec2(diff / 2.0, 0.0))
+                .color(overlay)
+                .weight(weight);
+            canvas
+                .line()
+                .start(mouse_pos - vec2(0.0, diff / 2.0))
+                .end(mouse_pos + vec2(0.0, diff / 2.0))
+                .color(overlay)
+                .weight(weight);
         }
     }
 
-    // Draw pixels over mouse
-    let mut mouse_pos = Point2::new(
-        ((app.mouse.position().x / diff).floor() + 0.5) * diff,
-        ((app.mouse.position().y / diff).floor() + 0.5) * diff,
-    );
+    // Draw the center guides
+    if model.settings.guides {
+        let document = model.state.document();
+        let end = vec2(document.width as f32, document.height as f32) / 2.0 * diff;
+        let (start, mid) = (-end, 0.0);
+        let weight = (diff / 10.0).max(1.0);
 
-    match model.settings.brush {
-        Brush::Square => {
-            // We need to manually align the pixels to the
-            // mouse if its even since theres no center
-            if model.settings.brush_size % 2 == 0 {
-                mouse_pos.x -= diff / 2.0;
-                mouse_pos.y -= diff / 2.0;
+        // Stand out more while strokes are being mirrored
+        let color = if model.settings.symmetry_segments > 1 {
+            Rgba8 {
+                alpha: 220,
+                ..GUIDE
             }
+        } else {
+            GUIDE
+        };
+        canvas
+            .line()
+            .start(pt2(mid, start.y))
+            .end(pt2(mid, end.y))
+            .color(color)
+            .weight(weight);
+        canvas
+            .line()
+            .start(pt2(start.x, mid))
+            .end(pt2(end.x, mid))
+            .color(color)
+            .weight(weight);
+    }
 
-            draw.rect().xy(mouse_pos).color(OVERLAY).w_h(
-                diff * model.settings.brush_size as f32,
-                diff * model.settings.brush_size as f32,
-            );
+    // Draw selection outline
+    if let Some(mask) = &model.state.document().selection {
+        let document = model.state.document();
+        let h = vec2(document.width as f32, document.height as f32) / 2.0;
+        for (start, end) in selection::outline(mask) {
+            canvas
+                .line()
+                .start((start - h) * diff)
+                .end((end - h) * diff)
+                .color(SELECTION)
+                .weight((diff / 10.0).max(1.0));
         }
-        Brush::Circle => {
-            for (x, y) in calc_circle_pixels(model.settings.brush_size as i32) {
-                draw.rect()
-                    .color(OVERLAY)
-                    .x_y(x as f32 * diff + mouse_pos.x, y as f32 * diff + mouse_pos.y)
-                    .w_h(diff, diff);
-            }
+    }
+
+    // Draw rulers along the top and left of the window
+    if model.settings.rulers {
+        let win = app.window_rect();
+        let document = model.state.document();
+        let (width, height) = (document.width, document.height);
+        let h = vec2(width as f32, height as f32) / 2.0;
+        let top = win.top() - model.state.top_inset;
+        let step = ((RULER_SIZE * 1.5 / diff).ceil() as usize).max(1);
+
+        draw.rect()
+            .x_y(0.0, top - RULER_SIZE / 2.0)
+            .w_h(win.w(), RULER_SIZE)
+            .color(RULER);
+        draw.rect()
+            .x_y(win.left() + RULER_SIZE / 2.0, 0.0)
+            .w_h(RULER_SIZE, win.h())
+            .color(RULER);
+
+        for i in (0..width).step_by(step) {
+            let edge = (i as f32 - h.x) * diff + offset.x;
+            let center = cell_center(i as i32, 0, width, height, diff) + offset;
+            let (label, _) = model.settings.coordinates(i as i32, 0, width, height);
+
+            draw.line()
+                .start(pt2(edge, top))
+                .end(pt2(edge, top - RULER_SIZE / 3.0))
+                .color(WHITE);
+            draw.text(&label.to_string())
+                .x_y(center.x, top - RULER_SIZE / 2.0)
+                .font_size(10)
+                .color(WHITE);
+        }
+
+        for i in (0..height).step_by(step) {
+            let edge = (i as f32 - h.y) * diff + offset.y;
+            let center = cell_center(0, i as i32, width, height, diff) + offset;
+            let (_, label) = model.settings.coordinates(0, i as i32, width, height);
+
+            // The vertical ruler ticks the bottom of each cell
+            // since the grid's y axis points up
+            draw.line()
+                .start(pt2(win.left(), edge))
+                .end(pt2(win.left() + RULER_SIZE / 3.0, edge))
+                .color(WHITE);
+            draw.text(&label.to_string())
+                .x_y(win.left() + RULER_SIZE / 2.0, center.y)
+                .font_size(10)
+                .color(WHITE);
         }
     }
 
+    // Draw keyboard cursor
+    if model.settings.keyboard_cursor {
+        let pixel =
+            &model.state.document().canvas.pixels[model.state.cursor.0][model.state.cursor.1];
+        canvas
+            .rect()
+            .x_y(pixel.x, pixel.y)
+            .w_h(diff, diff)
+            .no_fill()
+            .stroke(ORANGE)
+            .stroke_weight((diff / 8.0).max(1.0));
+    }
+
     // Finish drawing
     draw.to_frame(app, &frame).unwrap();
     model.egui.draw_to_frame(&frame).unwrap()
 }
 
 /// Implementation of Friedrich Gauss' solution
-/// to the Gauss circle problem.
-fn calc_circle_pixels(diameter: i32) -> Vec<(i32, i32)> {
+/// to the Gauss circle problem. A positive `bias` takes in
+/// more of the cells on the edge and a negative one fewer,
+/// with 1.0 matching the common `r * r + r` pixel art circle.
+fn calc_circle_pixels(diameter: i32, bias: f32) -> Vec<(i32, i32)> {
     let radius_f32 = diameter as f32 / 2.0;
     let radius = radius_f32 as i32;
+    // The center cell is always painted however low the bias goes
+    let limit = (radius_f32 * radius_f32 + radius_f32 * bias).max(0.0);
     let mut points = Vec::new();
 
     for x in -radius..=radius {
         for y in -radius..=radius {
             let x_f32 = x as f32;
             let y_f32 = y as f32;
-            if x_f32 * x_f32 + y_f32 * y_f32 <= radius_f32 * radius_f32 {
+            if x_f32 * x_f32 + y_f32 * y_f32 <= limit {
                 points.push((x, y));
             }
         }
@@ -436,3 +3694,721 @@ fn calc_circle_pixels(diameter: i32) -> Vec<(i32, i32)> {
 
     points
 }
+
+/// Saves the current tool and brush size so the next session
+/// starts with them.
+fn remember_tool(model: &mut Model) {
+    model.config.brush = Some(model.settings.brush.tag().to_string());
+    model.config.brush_size = Some(model.settings.brush_size);
+    if let Err(e) = model.config.save() {
+        eprintln!("Failed to save config: {e}");
+    }
+}
+
+/// Opens a project or imports an image into the current canvas,
+/// depending on the file's extension.
+fn open_file(model: &mut Model, path: &Path) {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("apd") => model.state.should_open_project = true,
+        Some("png") => model.state.should_import_image = true,
+        _ => {
+            model.state.status = Some(format!(
+                "Can't open {}, only .apd and .png files are supported",
+                path.display()
+            ));
+            return;
+        }
+    }
+    model.state.document_mut().file_name = path.with_extension("").display().to_string();
+    model.state.open_path = Some(path.to_path_buf());
+}
+
+/// Clears the canvas to the fill color. If the grid was resized
+/// the other layers are cleared too and the brush sizes are
+/// clamped to fit, returning a message if they had to shrink.
+fn reset_canvas(document: &mut Document, settings: &mut Settings) -> Option<String> {
+    let (width, height) = (document.width, document.height);
+    document.selection = None;
+    if (document.canvas.width(), document.canvas.height()) == (width, height) {
+        document.history.begin("Reset", &document.canvas.pixels);
+        document.canvas.reset(width, height, settings.fill_color);
+        document.history.commit(&document.canvas.pixels);
+        return None;
+    }
+
+    // The recorded cells no longer fit the new size
+    document.history.clear();
+    document.canvas.reset(width, height, settings.fill_color);
+    let active = document.active_layer;
+    for (i, layer) in document.layers.iter_mut().enumerate() {
+        if i != active {
+            *layer = Layer {
+                name: std::mem::take(&mut layer.name),
+                ..Layer::new(String::new(), width, height, settings.background_color)
+            };
+        }
+    }
+
+    let sizes = (settings.brush_size, settings.eraser_size);
+    let longest = document.longest_side();
+    settings.clamp_sizes(longest);
+    (sizes != (settings.brush_size, settings.eraser_size))
+        .then(|| format!("Brush size clamped to {longest}"))
+}
+
+/// Adds a project to the recent projects and saves the config.
+fn remember_project(model: &mut Model, path: PathBuf) {
+    model.config.add_recent(path);
+    if let Err(e) = model.config.save() {
+        eprintln!("Failed to save config: {e}");
+    }
+}
+
+/// Moves the keyboard cursor or paints the cell under it.
+fn use_cursor(model: &mut Model, key: Key) {
+    let document = model.state.document();
+    let (max_x, max_y) = (document.width - 1, document.height - 1);
+    let (x, y) = model.state.cursor;
+
+    match key {
+        Key::Left => model.state.cursor.0 = x.saturating_sub(1),
+        Key::Right => model.state.cursor.0 = (x + 1).min(max_x),
+        Key::Down => model.state.cursor.1 = y.saturating_sub(1),
+        Key::Up => model.state.cursor.1 = (y + 1).min(max_y),
+        Key::Space => {
+            let document = &mut model.state.documents[model.state.active];
+            let color = model.settings.primary_color;
+            paint_cursor(document, &model.settings, "Draw", (x, y), color);
+        }
+        Key::Back => {
+            let document = &mut model.state.documents[model.state.active];
+            let color = model.settings.secondary_color;
+            paint_cursor(document, &model.settings, "Erase", (x, y), color);
+        }
+        _ => (),
+    }
+}
+
+/// Paints the cell under the keyboard cursor, leaving it alone if
+/// its color is locked.
+fn paint_cursor(
+    document: &mut Document,
+    settings: &Settings,
+    label: &'static str,
+    (x, y): (usize, usize),
+    color: Rgb8,
+) {
+    let (x, y) = (x as i32, y as i32);
+    if document
+        .canvas
+        .color(x, y)
+        .is_none_or(|old| settings.is_locked(old))
+    {
+        return;
+    }
+
+    document.history.begin(label, &document.canvas.pixels);
+    document.canvas.paint_cell(x, y, color);
+    document.history.commit(&document.canvas.pixels);
+}
+
+/// Flood fills from the given cell, keeping inside the selection if
+/// there is one. Returns how many cells changed.
+fn fill_cell(document: &mut Document, settings: &Settings, x: i32, y: i32, color: Rgb8) -> usize {
+    // The filled cells all share the first one's color, so they are
+    // either all locked or none are
+    if document
+        .canvas
+        .color(x, y)
+        .is_none_or(|old| settings.is_locked(old))
+    {
+        return 0;
+    }
+
+    document
+        .canvas
+        .fill(x, y, color, document.selection.as_ref())
+}
+
+/// Enlarges the text and controls and replaces the theme's muted
+/// colors with solid black and white, for users with low vision.
+fn high_contrast(style: &mut egui::Style, dark: bool) {
+    let (fg, bg) = if dark {
+        (egui::Color32::WHITE, egui::Color32::BLACK)
+    } else {
+        (egui::Color32::BLACK, egui::Color32::WHITE)
+    };
+
+    for font in style.text_styles.values_mut() {
+        font.size *= 1.4;
+    }
+    style.spacing.item_spacing *= 1.5;
+    style.spacing.button_padding *= 1.5;
+    style.spacing.interact_size *= 1.5;
+    style.spacing.slider_width *= 1.5;
+    style.spacing.icon_width *= 1.5;
+
+    let visuals = &mut style.visuals;
+    visuals.override_text_color = Some(fg);
+    visuals.window_fill = bg;
+    visuals.panel_fill = bg;
+    visuals.window_stroke = egui::Stroke::new(2.0, fg);
+    visuals.selection.bg_fill = egui::Color32::from_rgb(0, 90, 255);
+    visuals.selection.stroke = egui::Stroke::new(2.0, fg);
+    visuals.hyperlink_color = egui::Color32::from_rgb(0, 140, 255);
+    for widget in [
+        &mut visuals.widgets.noninteractive,
+        &mut visuals.widgets.inactive,
+        &mut visuals.widgets.hovered,
+        &mut visuals.widgets.active,
+        &mut visuals.widgets.open,
+    ] {
+        widget.bg_stroke = egui::Stroke::new(2.0, fg);
+        widget.fg_stroke = egui::Stroke::new(2.0, fg);
+    }
+    visuals.widgets.noninteractive.bg_fill = bg;
+    visuals.widgets.inactive.bg_fill = bg;
+    visuals.widgets.inactive.weak_bg_fill = bg;
+}
+
+/// Returns the size of a cell in points. With crisp rendering it is
+/// rounded down to a whole number of device pixels so that no cell
+/// edge lands between pixels. Grids with an odd side are centered on
+/// the middle of a cell, so they need an even number of pixels per cell.
+fn cell_size(app: &App, settings: &Settings, document: &Document) -> f32 {
+    let win = app.window_rect();
+    let diff =
+        (win.w() / document.width as f32).min(win.h() / document.height as f32) * document.zoom;
+    if !settings.crisp {
+        return diff;
+    }
+
+    let scale = app.main_window().scale_factor();
+    let pixels = if document.width % 2 == 1 || document.height % 2 == 1 {
+        ((diff * scale / 2.0).floor() * 2.0).max(2.0)
+    } else {
+        (diff * scale).floor().max(1.0)
+    };
+    pixels / scale
+}
+
+/// Returns the position of the mouse in points, measured
+/// from the center of the panned canvas.
+fn canvas_mouse(app: &App, document: &Document, diff: f32) -> Point2 {
+    app.mouse.position() - document.pan * diff
+}
+
+/// Converts a position in the window to cells, measured
+/// from the bottom left corner of the grid.
+fn window_to_cells(position: Point2, document: &Document, diff: f32) -> Point2 {
+    let half = vec2(document.width as f32, document.height as f32) / 2.0;
+    (position - document.pan * diff) / diff + half
+}
+
+/// Returns the position of the mouse in cells, measured
+/// from the bottom left corner of the grid.
+fn mouse_point(app: &App, document: &Document, diff: f32) -> Point2 {
+    window_to_cells(app.mouse.position(), document, diff)
+}
+
+/// Returns the grid cell containing a point given in cells.
+fn point_cell(point: Point2) -> (i32, i32) {
+    (point.x.floor() as i32, point.y.floor() as i32)
+}
+
+/// Returns the grid cell under the mouse. The cell
+/// may lie outside of the grid.
+fn mouse_cell(app: &App, document: &Document, diff: f32) -> (i32, i32) {
+    point_cell(mouse_point(app, document, diff))
+}
+
+/// Draws a line of cells in `color`, or blended from the primary
+/// color at `start` to the secondary at `end` for gradients.
+fn draw_line(model: &mut Model, start: (i32, i32), end: (i32, i32), color: Rgb8, gradient: bool) {
+    let settings = &model.settings;
+    let changed = model
+        .state
+        .document_mut()
+        .canvas
+        .stroke(start, end, |t, old| {
+            if settings.is_locked(old) {
+                None
+            } else if gradient {
+                Some(palette::lerp(
+                    settings.primary_color,
+                    settings.secondary_color,
+                    t,
+                ))
+            } else {
+                Some(color)
+            }
+        });
+    *model.state.stroke_cells.get_or_insert(0) += changed;
+}
+
+/// Rounds a cell to the nearest one whose coordinates
+/// are both multiples of `step`.
+fn snap_cell(cell: (i32, i32), step: usize) -> (i32, i32) {
+    let step = step.max(1) as f32;
+    let snap = |v: i32| ((v as f32 / step).round() * step) as i32;
+    (snap(cell.0), snap(cell.1))
+}
+
+/// Returns the position of the center of the given cell,
+/// measured from the center of the canvas.
+fn cell_center(x: i32, y: i32, width: usize, height: usize, diff: f32) -> Point2 {
+    let (half_width, half_height) = (width as f32 / 2.0, height as f32 / 2.0);
+    Point2::new(
+        (x as f32 + 0.5 - half_width) * diff,
+        (y as f32 + 0.5 - half_height) * diff,
+    )
+}
+
+/// Paints the current brush at the given size centered on the given
+/// cell, ignoring any part of it that lies outside of the grid.
+/// `color` is called once for every cell painted. Returns how many
+/// cells changed color.
+///
+/// Each cell is set with `Canvas::paint_cell`, but the brush itself
+/// stays out here as its shape, symmetry and locking all come from
+/// the settings, which the canvas knows nothing about.
+fn paint<F>(
+    canvas: &mut Canvas,
+    settings: &Settings,
+    size: usize,
+    pos_x: i32,
+    pos_y: i32,
+    color: &mut F,
+) -> usize
+where
+    F: FnMut() -> Rgb8,
+{
+    let (width, height) = (canvas.width(), canvas.height());
+    let segments = settings.symmetry_segments.max(1);
+    let mut changed = 0;
+    let mut set = |x: i32, y: i32, opacity: f32| {
+        let color = color();
+        for (x, y) in rotations(x, y, width, height, segments) {
+            // Wrapping carries the brush over to the opposite edge
+            let (x, y) = if settings.wrap_strokes {
+                (x.rem_euclid(width as i32), y.rem_euclid(height as i32))
+            } else {
+                (x, y)
+            };
+            let Some(old) = canvas.color(x, y) else {
+                continue;
+            };
+
+            if settings.is_locked(old) {
+                continue;
+            }
+            // Painting behind leaves everything but the background alone
+            let behind = !settings.paint_behind || old == settings.background_color;
+            if behind && canvas.paint_cell(x, y, palette::lerp(old, color, opacity)) {
+                changed += 1;
+            }
+        }
+    };
+
+    // Soft brushes fade out from the center towards the edge
+    let radius = (size as f32 / 2.0).max(0.5);
+    for (x, y) in brush_cells(&settings.brush, size, settings.circle_bias, pos_x, pos_y) {
+        let distance = vec2((x - pos_x) as f32, (y - pos_y) as f32).length() / radius;
+        set(x, y, falloff(distance, settings.hardness));
+    }
+    changed
+}
+
+/// How opaque a soft brush is at a distance from its center, where 1.0
+/// is the edge. The brush is fully opaque out to `hardness` and fades
+/// away like a bell curve past it, so a hardness of 1.0 is uniform.
+fn falloff(distance: f32, hardness: f32) -> f32 {
+    if hardness >= 1.0 {
+        return 1.0;
+    }
+
+    let t = ((distance - hardness) / (1.0 - hardness)).clamp(0.0, 1.0);
+    (-4.0 * t * t).exp()
+}
+
+/// Returns the cells covered by a brush of the given size centered
+/// on the given cell. Painting and the preview both use this so they
+/// always agree. Cells may lie outside of the grid.
+fn brush_cells(
+    brush: &Brush,
+    size: usize,
+    circle_bias: f32,
+    pos_x: i32,
+    pos_y: i32,
+) -> Vec<(i32, i32)> {
+    match brush {
+        Brush::Square => {
+            let size = size as i32;
+            let start_x = pos_x - size / 2;
+            let start_y = pos_y - size / 2;
+            (start_x..start_x + size)
+                .flat_map(|x| (start_y..start_y + size).map(move |y| (x, y)))
+                .collect()
+        }
+        Brush::Circle => calc_circle_pixels(size as i32, circle_bias)
+            .into_iter()
+            .map(|(x, y)| (x + pos_x, y + pos_y))
+            .collect(),
+        Brush::Fill | Brush::Line | Brush::Lasso | Brush::Wand | Brush::Eyedropper => {
+            vec![(pos_x, pos_y)]
+        }
+    }
+}
+
+/// Returns the cells the eyedropper samples, which is a square the
+/// size of the brush when averaging and the given cell otherwise.
+fn eyedropper_cells(settings: &Settings, size: usize, x: i32, y: i32) -> Vec<(i32, i32)> {
+    if settings.eyedropper_average {
+        brush_cells(&Brush::Square, size, 0.0, x, y)
+    } else {
+        vec![(x, y)]
+    }
+}
+
+/// Returns the given cell rotated about the center of the grid by
+/// each of `segments` evenly spaced angles, starting with the cell
+/// itself. Rotated cells may lie outside of the grid.
+fn rotations(x: i32, y: i32, width: usize, height: usize, segments: usize) -> Vec<(i32, i32)> {
+    let center = vec2(width as f32, height as f32) / 2.0;
+    let offset = vec2(x as f32 + 0.5, y as f32 + 0.5) - center;
+
+    (0..segments)
+        .map(|i| {
+            let angle = i as f32 / segments as f32 * f32::consts::TAU;
+            let rotated = offset.rotate(angle) + center - 0.5;
+            (rotated.x.round() as i32, rotated.y.round() as i32)
+        })
+        .collect()
+}
+
+/// Snaps `end` to the nearest horizontal, vertical or
+/// diagonal line through `start`.
+fn constrain_line(start: (i32, i32), end: (i32, i32)) -> (i32, i32) {
+    let dx = end.0 - start.0;
+    let dy = end.1 - start.1;
+    let (abs_x, abs_y) = (dx.abs() as f32, dy.abs() as f32);
+
+    // tan(22.5°) splits the space evenly between the 8 directions
+    if abs_y < abs_x * 0.414 {
+        (end.0, start.1)
+    } else if abs_x < abs_y * 0.414 {
+        (start.0, end.1)
+    } else {
+        let len = dx.abs().max(dy.abs());
+        (start.0 + len * dx.signum(), start.1 + len * dy.signum())
+    }
+}
+
+/// Splits a column of cells into runs of the same color so each can
+/// be drawn as one rectangle, giving the index of each run's first
+/// cell and its length. With `split` every cell is a run of its own.
+fn color_runs(column: &[Pixel], split: bool) -> Vec<(usize, usize)> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    while let Some(first) = column.get(start) {
+        let len = if split {
+            1
+        } else {
+            column[start..]
+                .iter()
+                .take_while(|pixel| pixel.color == first.color)
+                .count()
+        };
+        runs.push((start, len));
+        start += len;
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shrinking_the_grid_clamps_the_brush() {
+        let mut settings = Settings {
+            brush_size: 12,
+            eraser_size: 9,
+            ..Default::default()
+        };
+        let mut document = Document::new(16, 16, String::from("drawing"), BLACK);
+
+        // Resizing from the settings sets the size and resets
+        (document.width, document.height) = (4, 4);
+        let status = reset_canvas(&mut document, &mut settings);
+        assert_eq!(status.as_deref(), Some("Brush size clamped to 4"));
+        assert_eq!((settings.brush_size, settings.eraser_size), (4, 4));
+        assert_eq!((document.canvas.width(), document.canvas.height()), (4, 4));
+
+        // Painting near the edges with the clamped sizes stays in the grid
+        settings.brush = Brush::Square;
+        for (x, y) in [(0, 0), (4, 4)] {
+            let size = settings.brush_size;
+            paint(&mut document.canvas, &settings, size, x, y, &mut || WHITE);
+        }
+        assert_eq!(
+            canvas_picture(&document.canvas),
+            ["..##", "..##", "##..", "##.."]
+        );
+
+        // Resetting at the same size leaves the brush alone
+        assert_eq!(reset_canvas(&mut document, &mut settings), None);
+        assert_eq!(
+            canvas_picture(&document.canvas),
+            ["....", "....", "....", "...."]
+        );
+
+        settings.brush = Brush::Circle;
+        let size = settings.brush_size;
+        paint(&mut document.canvas, &settings, size, 3, 0, &mut || WHITE);
+        assert_eq!(
+            canvas_picture(&document.canvas),
+            ["....", "...#", "..##", ".###"]
+        );
+    }
+
+    #[test]
+    fn locked_colors_are_not_painted_over() {
+        let settings = Settings {
+            locked_colors: vec![WHITE],
+            ..Default::default()
+        };
+        let mut document = Document::new(2, 2, String::from("drawing"), BLACK);
+        document.canvas.paint_cell(0, 0, WHITE);
+
+        paint_cursor(&mut document, &settings, "Draw", (0, 0), RED);
+        assert_eq!(fill_cell(&mut document, &settings, 0, 0, RED), 0);
+        assert_eq!(document.canvas.color(0, 0), Some(WHITE));
+        assert_eq!(document.history.next_undo(), None);
+
+        // Unlocked cells still take the paint, and fills stop at locks
+        paint_cursor(&mut document, &settings, "Draw", (1, 1), RED);
+        assert_eq!(document.canvas.color(1, 1), Some(RED));
+        assert_eq!(fill_cell(&mut document, &settings, 1, 0, RED), 1);
+
+        adjust::adjust(
+            &mut document.canvas.pixels,
+            adjust::Adjustment::Fill(RED),
+            None,
+            |color| settings.is_locked(color),
+        );
+        assert_eq!(document.canvas.color(0, 0), Some(WHITE));
+        assert_eq!(document.canvas.color(0, 1), Some(RED));
+    }
+
+    #[test]
+    fn undoing_a_merge_restores_both_layers() {
+        let mut document = Document::new(2, 2, String::from("drawing"), BLACK);
+        document.canvas.paint_cell(0, 0, WHITE);
+        document.add_layer(BLACK);
+        document.canvas.paint_cell(1, 1, WHITE);
+        document.layers[1].blend = layer::Blend::Screen;
+        document.layers[1].opacity = 0.5;
+
+        document.merge_down(BLACK);
+        assert_eq!(document.layers.len(), 1);
+        let merged = document.canvas.color(1, 1);
+        assert_ne!(merged, Some(BLACK));
+
+        assert_eq!(document.undo(), Some("Merge Down"));
+        assert_eq!(document.layers.len(), 2);
+        assert_eq!(document.active_layer, 0);
+        assert_eq!(document.canvas.color(0, 0), Some(WHITE));
+        assert_eq!(document.canvas.color(1, 1), Some(BLACK));
+        let above = &document.layers[1];
+        assert_eq!(above.name, "Layer 2");
+        assert_eq!(above.pixels[1][1].color, WHITE);
+        assert!(above.blend == layer::Blend::Screen);
+        assert_eq!(above.opacity, 0.5);
+
+        // Redoing takes the layer off the stack again
+        assert_eq!(document.redo(), Some("Merge Down"));
+        assert_eq!(document.layers.len(), 1);
+        assert_eq!(document.canvas.color(1, 1), merged);
+    }
+
+    /// Draws a square canvas like `picture`, with `#` for its white cells.
+    fn canvas_picture(canvas: &Canvas) -> Vec<String> {
+        let size = canvas.width() as i32;
+        let white: Vec<(i32, i32)> = (0..size)
+            .flat_map(|x| (0..size).map(move |y| (x, y)))
+            .filter(|&(x, y)| canvas.color(x, y) == Some(WHITE))
+            .collect();
+        picture_within(&white, 0..=size - 1)
+    }
+
+    #[test]
+    fn canvas_strokes_and_flips() {
+        let mut canvas = Canvas::new(4, 4, BLACK);
+        assert_eq!(canvas.stroke((0, 0), (3, 3), |_, _| Some(WHITE)), 4);
+        assert_eq!(canvas_picture(&canvas), ["...#", "..#.", ".#..", "#..."]);
+
+        // Painting over the same cells again changes nothing, and
+        // cells past the edge are skipped
+        assert_eq!(canvas.stroke((0, 0), (3, 3), |_, _| Some(WHITE)), 0);
+        assert_eq!(canvas.stroke((5, 0), (4, 0), |_, _| Some(WHITE)), 0);
+
+        canvas.reset(4, 4, BLACK);
+        canvas.stroke((0, 0), (1, 0), |_, _| Some(WHITE));
+        canvas.flip(canvas::Flip::Horizontal);
+        assert_eq!(canvas_picture(&canvas), ["....", "....", "....", "..##"]);
+        canvas.flip(canvas::Flip::Vertical);
+        assert_eq!(canvas_picture(&canvas), ["..##", "....", "....", "...."]);
+    }
+
+    #[test]
+    fn canvas_fill_stays_in_its_region() {
+        let mut canvas = Canvas::new(4, 4, BLACK);
+        canvas.stroke((0, 2), (3, 2), |_, _| Some(WHITE));
+
+        // The line splits the canvas, so only the cells below it fill
+        assert_eq!(canvas.fill(3, 0, WHITE, None), 8);
+        assert_eq!(canvas_picture(&canvas), ["....", "####", "####", "####"]);
+        assert_eq!(canvas.fill(-1, 0, WHITE, None), 0);
+    }
+
+    #[test]
+    fn color_runs_stay_within_their_column() {
+        // Columns are deliberately longer than the grid is wide
+        let column = |colors: &[Rgb8]| -> Vec<Pixel> {
+            colors
+                .iter()
+                .map(|&color| Pixel {
+                    color,
+                    ..Default::default()
+                })
+                .collect()
+        };
+        let pixels = [
+            column(&[BLACK, BLACK, WHITE, WHITE, WHITE, BLACK]),
+            column(&[WHITE; 6]),
+        ];
+
+        assert_eq!(color_runs(&pixels[0], false), [(0, 2), (2, 3), (5, 1)]);
+        assert_eq!(color_runs(&pixels[1], false), [(0, 6)]);
+        assert_eq!(
+            color_runs(&pixels[1], true),
+            (0..6).map(|y| (y, 1)).collect::<Vec<_>>()
+        );
+        assert!(color_runs(&[], false).is_empty());
+    }
+
+    /// Draws a set of cells as rows of `#` and `.`, top row first.
+    fn picture(cells: &[(i32, i32)]) -> Vec<String> {
+        let radius = cells
+            .iter()
+            .map(|&(x, y)| x.abs().max(y.abs()))
+            .max()
+            .unwrap();
+        picture_within(cells, -radius..=radius)
+    }
+
+    /// Draws the cells whose coordinates both lie in `range`.
+    fn picture_within(cells: &[(i32, i32)], range: std::ops::RangeInclusive<i32>) -> Vec<String> {
+        range
+            .clone()
+            .rev()
+            .map(|y| {
+                range
+                    .clone()
+                    .map(|x| if cells.contains(&(x, y)) { '#' } else { '.' })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn circle_cells() {
+        assert_eq!(picture(&calc_circle_pixels(1, 0.0)), ["#"]);
+        assert_eq!(picture(&calc_circle_pixels(2, 0.0)), [".#.", "###", ".#."]);
+        assert_eq!(picture(&calc_circle_pixels(3, 0.0)), ["###", "###", "###"]);
+        assert_eq!(
+            picture(&calc_circle_pixels(4, 0.0)),
+            ["..#..", ".###.", "#####", ".###.", "..#.."]
+        );
+        assert_eq!(
+            picture(&calc_circle_pixels(5, 0.0)),
+            [".###.", "#####", "#####", "#####", ".###."]
+        );
+
+        // Biasing takes in more or fewer of the edge cells
+        assert_eq!(
+            picture(&calc_circle_pixels(4, 1.0)),
+            [".###.", "#####", "#####", "#####", ".###."]
+        );
+        assert_eq!(picture(&calc_circle_pixels(2, -1.0)), ["#"]);
+        assert_eq!(picture(&calc_circle_pixels(1, -1.0)), ["#"]);
+    }
+
+    #[test]
+    fn clicked_cells_match_exported_pixels() {
+        let mut document = Document::new(4, 4, String::from("drawing"), BLACK);
+        document.pan = Point2::new(1.0, 0.0);
+        let diff = 10.0;
+
+        // Window positions with the buffer indices they land on, the
+        // top left origin they should read as, which are the indices
+        // of the pixels in exported images, and the center origin ones
+        let clicks = [
+            (Point2::new(-5.0, 15.0), (0, 3), (0, 0), (-2, 1)),
+            (Point2::new(15.0, -5.0), (2, 1), (2, 2), (0, -1)),
+            (Point2::new(25.0, -15.0), (3, 0), (3, 3), (1, -2)),
+        ];
+        for (position, index, top_left, center) in clicks {
+            let (x, y) = point_cell(window_to_cells(position, &document, diff));
+            assert_eq!(document.canvas.index(x, y), Some(index));
+            assert_eq!(Origin::TopLeft.coordinates(x, y, 4, 4), top_left);
+            assert_eq!(Origin::Center.coordinates(x, y, 4, 4), center);
+
+            assert!(document.canvas.paint_cell(x, y, WHITE));
+            assert_eq!(document.canvas.pixels[index.0][index.1].color, WHITE);
+            let image = export::render(&document.canvas.pixels);
+            let pixel = image.get_pixel(top_left.0 as u32, top_left.1 as u32);
+            assert_eq!(pixel.0, [255; 3]);
+            document.canvas.paint_cell(x, y, BLACK);
+        }
+
+        // Clicks past the edges land on no cell
+        for position in [Point2::new(35.0, 0.0), Point2::new(0.0, -21.0)] {
+            let (x, y) = point_cell(window_to_cells(position, &document, diff));
+            assert_eq!(document.canvas.index(x, y), None);
+            assert!(!document.canvas.paint_cell(x, y, WHITE));
+        }
+
+        // The bottom row of the canvas is the last row of an image
+        assert_eq!(canvas::image_row(0, 4), 3);
+        assert_eq!(canvas::image_row(canvas::image_row(1, 4), 4), 1);
+    }
+
+    #[test]
+    fn tiny_grids_are_centered() {
+        let diff = 10.0;
+        assert_eq!(cell_center(0, 0, 1, 1, diff), Point2::ZERO);
+
+        for grid_size in 1..=3 {
+            let last = grid_size as i32 - 1;
+            let first = cell_center(0, 0, grid_size, grid_size, diff);
+            assert_eq!(first, -cell_center(last, last, grid_size, grid_size, diff));
+
+            // Neighbouring cells sit exactly one cell apart
+            for x in 0..last {
+                let next = cell_center(x + 1, x + 1, grid_size, grid_size, diff);
+                assert_eq!(
+                    next - cell_center(x, x, grid_size, grid_size, diff),
+                    Point2::splat(diff)
+                );
+            }
+        }
+
+        // Each side of a rectangular grid is centered on its own
+        let (width, height) = (3, 2);
+        let first = cell_center(0, 0, width, height, diff);
+        assert_eq!(first, -cell_center(2, 1, width, height, diff));
+        assert_eq!(first, Point2::new(-diff, -diff / 2.0));
+    }
+}
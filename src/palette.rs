@@ -0,0 +1,325 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use nannou::prelude::*;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PaletteFormat {
+    Gpl,
+    Hex,
+}
+
+impl PaletteFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PaletteFormat::Gpl => "gpl",
+            PaletteFormat::Hex => "hex",
+        }
+    }
+}
+
+/// Collects every distinct color on the canvas, sorted
+/// by hue and then by value. If `skip` is given that
+/// color is left out of the result.
+pub fn collect_colors<'a, I>(colors: I, skip: Option<Rgb8>) -> Vec<Rgb8>
+where
+    I: IntoIterator<Item = &'a Rgb8>,
+{
+    let mut seen = HashSet::new();
+    let mut colors: Vec<Rgb8> = colors
+        .into_iter()
+        .copied()
+        .filter(|color| Some(*color) != skip)
+        .filter(|color| seen.insert((color.red, color.green, color.blue)))
+        .collect();
+
+    colors.sort_by(|a, b| {
+        let (a_hue, _, a_value) = rgb_to_hsv(*a);
+        let (b_hue, _, b_value) = rgb_to_hsv(*b);
+        a_hue
+            .total_cmp(&b_hue)
+            .then_with(|| a_value.total_cmp(&b_value))
+    });
+
+    colors
+}
+
+/// Counts how many cells use each color, most used first. If
+/// `skip` is given that color is left out of the result.
+pub fn count_colors<'a, I>(colors: I, skip: Option<Rgb8>) -> Vec<(Rgb8, usize)>
+where
+    I: IntoIterator<Item = &'a Rgb8>,
+{
+    let mut counts = HashMap::new();
+    for color in colors.into_iter().filter(|color| Some(**color) != skip) {
+        *counts
+            .entry((color.red, color.green, color.blue))
+            .or_insert(0) += 1;
+    }
+
+    // Break ties by channel so the order doesn't jump around
+    let mut counts: Vec<((u8, u8, u8), usize)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
+        .into_iter()
+        .map(|((r, g, b), count)| (rgb8(r, g, b), count))
+        .collect()
+}
+
+/// Writes the colors to `path` in the given format.
+pub fn write_palette(path: &Path, colors: &[Rgb8], format: PaletteFormat) -> io::Result<()> {
+    let mut out = String::new();
+
+    match format {
+        PaletteFormat::Gpl => {
+            out.push_str("GIMP Palette\n");
+            out.push_str("Name: ap-drawing\n");
+            out.push_str("#\n");
+            for color in colors {
+                out.push_str(&format!(
+                    "{:3} {:3} {:3}\t{}\n",
+                    color.red,
+                    color.green,
+                    color.blue,
+                    to_hex(*color)
+                ));
+            }
+        }
+        PaletteFormat::Hex => {
+            for color in colors {
+                out.push_str(&to_hex(*color));
+                out.push('\n');
+            }
+        }
+    }
+
+    fs::write(path, out)
+}
+
+/// Reads a palette file written in either format, picked by
+/// the file's extension. Lines that aren't colors are skipped.
+pub fn read_palette(path: &Path) -> io::Result<Vec<Rgb8>> {
+    let contents = fs::read_to_string(path)?;
+    let gpl = path.extension().is_some_and(|ext| ext == "gpl");
+
+    Ok(contents
+        .lines()
+        .filter_map(|line| {
+            if gpl {
+                let mut channels = line.split_whitespace().map(|c| c.parse::<u8>());
+                match (channels.next(), channels.next(), channels.next()) {
+                    (Some(Ok(r)), Some(Ok(g)), Some(Ok(b))) => Some(rgb8(r, g, b)),
+                    _ => None,
+                }
+            } else {
+                from_hex(line)
+            }
+        })
+        .collect())
+}
+
+/// Reads the hex colors out of a list separated by commas, semicolons
+/// or whitespace, such as one copied from a palette website. Anything
+/// that isn't a color is skipped.
+pub fn parse_hex_list(text: &str) -> Vec<Rgb8> {
+    text.split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+        .filter_map(from_hex)
+        .collect()
+}
+
+/// Picks up to `count` colors representing the given ones by
+/// median cut: the box of colors with the widest channel is split
+/// at its median until there are enough boxes, and each box is
+/// then averaged into one color.
+pub fn median_cut(colors: &[Rgb8], count: usize) -> Vec<Rgb8> {
+    let channels = |color: &Rgb8| [color.red, color.green, color.blue];
+    let range = |colors: &[Rgb8], channel: usize| {
+        let values = colors.iter().map(|color| channels(color)[channel]);
+        values.clone().max().unwrap_or(0) - values.min().unwrap_or(0)
+    };
+    let widest = |colors: &[Rgb8]| {
+        (0..3)
+            .map(|channel| (channel, range(colors, channel)))
+            .max_by_key(|&(_, range)| range)
+            .unwrap_or((0, 0))
+    };
+
+    let mut boxes = vec![colors.to_vec()];
+    boxes.retain(|colors| !colors.is_empty());
+    while boxes.len() < count {
+        // Stop once no box has more than one shade left to split
+        let Some((index, (channel, _))) = boxes
+            .iter()
+            .map(|colors| widest(colors))
+            .enumerate()
+            .filter(|(_, (_, range))| *range > 0)
+            .max_by_key(|(_, (_, range))| *range)
+        else {
+            break;
+        };
+
+        let mut colors = boxes.swap_remove(index);
+        colors.sort_by_key(|color| channels(color)[channel]);
+        let upper = colors.split_off(colors.len() / 2);
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+
+    boxes.iter().map(|colors| average(colors)).collect()
+}
+
+/// Returns the mean of the given colors on each channel,
+/// or black if there are none.
+pub fn average(colors: &[Rgb8]) -> Rgb8 {
+    let mut sum = [0u32; 3];
+    for color in colors {
+        sum[0] += color.red as u32;
+        sum[1] += color.green as u32;
+        sum[2] += color.blue as u32;
+    }
+    let len = colors.len().max(1) as u32;
+    rgb8(
+        (sum[0] / len) as u8,
+        (sum[1] / len) as u8,
+        (sum[2] / len) as u8,
+    )
+}
+
+/// A handful of the CSS named colors, spread out enough
+/// that every color lands reasonably near one of them.
+const NAMES: [(&str, [u8; 3]); 32] = [
+    ("Black", [0, 0, 0]),
+    ("DimGray", [105, 105, 105]),
+    ("Gray", [128, 128, 128]),
+    ("Silver", [192, 192, 192]),
+    ("White", [255, 255, 255]),
+    ("Maroon", [128, 0, 0]),
+    ("Red", [255, 0, 0]),
+    ("Tomato", [255, 99, 71]),
+    ("Salmon", [250, 128, 114]),
+    ("Pink", [255, 192, 203]),
+    ("HotPink", [255, 105, 180]),
+    ("Crimson", [220, 20, 60]),
+    ("Brown", [165, 42, 42]),
+    ("Chocolate", [210, 105, 30]),
+    ("Tan", [210, 180, 140]),
+    ("Orange", [255, 165, 0]),
+    ("Gold", [255, 215, 0]),
+    ("Yellow", [255, 255, 0]),
+    ("Khaki", [240, 230, 140]),
+    ("Olive", [128, 128, 0]),
+    ("Lime", [0, 255, 0]),
+    ("Green", [0, 128, 0]),
+    ("DarkGreen", [0, 100, 0]),
+    ("SeaGreen", [46, 139, 87]),
+    ("Teal", [0, 128, 128]),
+    ("Cyan", [0, 255, 255]),
+    ("SkyBlue", [135, 206, 235]),
+    ("SteelBlue", [70, 130, 180]),
+    ("Blue", [0, 0, 255]),
+    ("Navy", [0, 0, 128]),
+    ("Purple", [128, 0, 128]),
+    ("Violet", [238, 130, 238]),
+];
+
+/// The squared distance between two colors.
+fn distance(color: Rgb8, other: Rgb8) -> i32 {
+    let r = color.red as i32 - other.red as i32;
+    let g = color.green as i32 - other.green as i32;
+    let b = color.blue as i32 - other.blue as i32;
+    r * r + g * g + b * b
+}
+
+/// Returns the color in `palette` closest to `color`.
+pub fn nearest(color: Rgb8, palette: &[Rgb8]) -> Rgb8 {
+    palette
+        .iter()
+        .copied()
+        .min_by_key(|&other| distance(color, other))
+        .unwrap_or(color)
+}
+
+/// Returns the name of the named color closest to `color`.
+pub fn name(color: Rgb8) -> &'static str {
+    NAMES
+        .iter()
+        .min_by_key(|(_, [r, g, b])| distance(color, rgb8(*r, *g, *b)))
+        .map_or("Black", |(name, _)| name)
+}
+
+/// Linearly interpolates between two colors on each channel.
+pub fn lerp(a: Rgb8, b: Rgb8, t: f32) -> Rgb8 {
+    let channel = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+    rgb8(
+        channel(a.red, b.red),
+        channel(a.green, b.green),
+        channel(a.blue, b.blue),
+    )
+}
+
+/// Returns how bright a color looks, weighting the
+/// channels by how sensitive the eye is to each.
+pub fn luminance(color: Rgb8) -> u8 {
+    ((color.red as u32 * 299 + color.green as u32 * 587 + color.blue as u32 * 114 + 500) / 1000)
+        as u8
+}
+
+/// Parses a `rrggbb` string, with or without a leading `#`.
+pub fn from_hex(hex: &str) -> Option<Rgb8> {
+    let hex = hex.trim().trim_start_matches('#');
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some(rgb8(channel(0)?, channel(2)?, channel(4)?))
+}
+
+/// Formats a color as a lowercase `rrggbb` string.
+pub fn to_hex(color: Rgb8) -> String {
+    format!("{:02x}{:02x}{:02x}", color.red, color.green, color.blue)
+}
+
+/// Converts a color to hue (in degrees), saturation and value.
+pub fn rgb_to_hsv(color: Rgb8) -> (f32, f32, f32) {
+    let r = color.red as f32 / 255.0;
+    let g = color.green as f32 / 255.0;
+    let b = color.blue as f32 / 255.0;
+
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+
+    let hue = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    let saturation = if max == 0.0 { 0.0 } else { delta / max };
+
+    (hue, saturation, max)
+}
+
+/// Converts hue (in degrees), saturation and value back to a color.
+pub fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> Rgb8 {
+    let chroma = value * saturation;
+    let hue = hue.rem_euclid(360.0) / 60.0;
+    let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+    let (r, g, b) = match hue as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = value - chroma;
+    let channel = |c: f32| ((c + m) * 255.0).round() as u8;
+    rgb8(channel(r), channel(g), channel(b))
+}
@@ -0,0 +1,77 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+
+/// A reusable drawing setup that can be switched to from any
+/// canvas. Every field is optional, so presets saved before a
+/// field existed still load and leave that setting alone.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preset {
+    /// The tool's tag, e.g. "square".
+    pub brush: Option<String>,
+    pub brush_size: Option<usize>,
+    pub primary_color: Option<[u8; 3]>,
+    pub secondary_color: Option<[u8; 3]>,
+    pub swatches: Option<Vec<[u8; 3]>>,
+    /// How many cells across the grid is.
+    pub grid_size: Option<usize>,
+    /// How many cells tall the grid is. Presets saved before grids
+    /// could be rectangular leave this out and are square.
+    pub grid_height: Option<usize>,
+    pub symmetry_segments: Option<usize>,
+}
+
+/// Returns the folder presets are kept in, next to the config.
+fn dir() -> PathBuf {
+    config::dir().join("presets")
+}
+
+/// Returns the names of every saved preset in alphabetical order.
+pub fn list() -> Vec<String> {
+    let Ok(entries) = fs::read_dir(dir()) else {
+        return Vec::new();
+    };
+
+    let mut names: Vec<String> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .filter_map(|path| Some(path.file_stem()?.to_string_lossy().into_owned()))
+        .collect();
+    names.sort();
+    names
+}
+
+/// Returns the file a preset is kept in. Names that could reach
+/// outside the presets folder, or be hidden in it, are rejected.
+fn path(name: &str) -> io::Result<PathBuf> {
+    if name.is_empty() || name.starts_with('.') || name.contains(std::path::is_separator) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "preset names can't be empty, start with a dot or contain slashes",
+        ));
+    }
+
+    // Appended rather than set as the extension, so names like
+    // "v1.5" keep their dots
+    Ok(dir().join(format!("{name}.json")))
+}
+
+pub fn save(name: &str, preset: &Preset) -> io::Result<()> {
+    let path = path(name)?;
+    fs::create_dir_all(dir())?;
+    fs::write(path, serde_json::to_string_pretty(preset)?)
+}
+
+pub fn load(name: &str) -> io::Result<Preset> {
+    let contents = fs::read_to_string(path(name)?)?;
+    Ok(serde_json::from_str(&contents)?)
+}
@@ -0,0 +1,197 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use nannou::image::imageops::{self, FilterType};
+use nannou::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::canvas::Pixel;
+use crate::export;
+use crate::layer::{Blend, Layer};
+
+/// The width and height of the thumbnail saved with each project.
+const THUMBNAIL_SIZE: u32 = 64;
+
+/// The longest side of the largest grid the settings allow.
+const MAX_GRID_SIZE: usize = 64;
+
+/// The contents of a `.apd` project file.
+#[derive(Serialize, Deserialize)]
+struct Project {
+    /// How many cells across the grid is.
+    grid_size: usize,
+    /// How many cells tall the grid is. Missing from projects saved
+    /// before grids could be rectangular, which are square.
+    #[serde(default)]
+    grid_height: Option<usize>,
+    /// Every visible layer flattened together.
+    pixels: Vec<Vec<[u8; 3]>>,
+    /// Missing from projects saved before the view was stored.
+    #[serde(default)]
+    view: Option<View>,
+    /// The layers from the bottom up. Projects saved before layers
+    /// were stored have none, and open as a single layer of `pixels`.
+    #[serde(default)]
+    layers: Vec<ProjectLayer>,
+    #[serde(default)]
+    active_layer: usize,
+}
+
+/// One layer of a project.
+#[derive(Serialize, Deserialize)]
+struct ProjectLayer {
+    name: String,
+    pixels: Vec<Vec<[u8; 3]>>,
+    visible: bool,
+    blend: Blend,
+    opacity: f32,
+}
+
+/// A project read back from a file.
+pub struct Opened {
+    pub width: usize,
+    pub height: usize,
+    /// The layers from the bottom up.
+    pub layers: Vec<Layer>,
+    /// Which of `layers` was being drawn on.
+    pub active_layer: usize,
+    pub view: Option<View>,
+}
+
+/// Where the canvas was viewed from when the project was saved.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct View {
+    pub zoom: f32,
+    pub pan: [f32; 2],
+}
+
+/// Returns the path of the thumbnail saved alongside a project.
+pub fn thumbnail_path(path: &Path) -> PathBuf {
+    path.with_extension("thumb.png")
+}
+
+fn colors(pixels: &[Vec<Pixel>]) -> Vec<Vec<[u8; 3]>> {
+    pixels
+        .iter()
+        .map(|column| {
+            column
+                .iter()
+                .map(|pixel| [pixel.color.red, pixel.color.green, pixel.color.blue])
+                .collect()
+        })
+        .collect()
+}
+
+fn cells(colors: Vec<Vec<[u8; 3]>>) -> Vec<Vec<Pixel>> {
+    colors
+        .into_iter()
+        .map(|column| {
+            column
+                .into_iter()
+                .map(|[r, g, b]| Pixel {
+                    color: rgb8(r, g, b),
+                    ..Default::default()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Writes the layers to `path` along with `pixels`, the layers
+/// flattened, and a thumbnail of them next to it.
+pub fn save<'a, I>(
+    path: &Path,
+    pixels: &[Vec<Pixel>],
+    layers: I,
+    active_layer: usize,
+    view: View,
+) -> io::Result<()>
+where
+    I: IntoIterator<Item = (&'a [Vec<Pixel>], &'a Layer)>,
+{
+    let project = Project {
+        grid_size: pixels.len(),
+        grid_height: Some(pixels.first().map_or(0, Vec::len)),
+        pixels: colors(pixels),
+        view: Some(view),
+        layers: layers
+            .into_iter()
+            .map(|(pixels, layer)| ProjectLayer {
+                name: layer.name.clone(),
+                pixels: colors(pixels),
+                visible: layer.visible,
+                blend: layer.blend,
+                opacity: layer.opacity,
+            })
+            .collect(),
+        active_layer,
+    };
+    fs::write(path, serde_json::to_string(&project)?)?;
+
+    // Scale small canvases up without blurring them
+    let image = export::render(pixels);
+    let filter = if image.width() < THUMBNAIL_SIZE {
+        FilterType::Nearest
+    } else {
+        FilterType::Triangle
+    };
+    imageops::resize(&image, THUMBNAIL_SIZE, THUMBNAIL_SIZE, filter)
+        .save(thumbnail_path(path))
+        .map_err(io::Error::other)
+}
+
+/// Reads the layers stored in the project at `path`, along
+/// with the view it was saved with if there is one.
+pub fn open(path: &Path) -> io::Result<Opened> {
+    let project: Project = serde_json::from_str(&fs::read_to_string(path)?)?;
+    let width = project.grid_size;
+    let height = project.grid_height.unwrap_or(width);
+    let fits = |colors: &[Vec<[u8; 3]>]| {
+        colors.len() == width && colors.iter().all(|column| column.len() == height)
+    };
+    let malformed = width == 0
+        || height == 0
+        || !fits(&project.pixels)
+        || !project.layers.iter().all(|layer| fits(&layer.pixels));
+    if malformed {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "project grid is malformed",
+        ));
+    }
+    if width > MAX_GRID_SIZE || height > MAX_GRID_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("project grid is larger than {MAX_GRID_SIZE} cells"),
+        ));
+    }
+
+    let layers: Vec<Layer> = if project.layers.is_empty() {
+        vec![Layer {
+            pixels: cells(project.pixels),
+            ..Layer::new(String::from("Layer 1"), 0, 0, BLACK)
+        }]
+    } else {
+        project
+            .layers
+            .into_iter()
+            .map(|layer| Layer {
+                pixels: cells(layer.pixels),
+                visible: layer.visible,
+                blend: layer.blend,
+                opacity: layer.opacity.clamp(0.0, 1.0),
+                ..Layer::new(layer.name, 0, 0, BLACK)
+            })
+            .collect()
+    };
+    let active_layer = project.active_layer.min(layers.len() - 1);
+
+    Ok(Opened {
+        width,
+        height,
+        layers,
+        active_layer,
+        view: project.view,
+    })
+}
@@ -0,0 +1,132 @@
+use nannou::prelude::*;
+
+/// Which cells are selected, indexed the same way as the canvas.
+pub type Mask = Vec<Vec<bool>>;
+
+/// How strongly each cell is selected, from 0.0 to 1.0.
+pub type Weights = Vec<Vec<f32>>;
+
+/// Builds a selection containing only the given cells.
+pub fn from_cells<I>(cells: I, width: usize, height: usize) -> Mask
+where
+    I: IntoIterator<Item = (usize, usize)>,
+{
+    let mut mask = vec![vec![false; height]; width];
+    for (x, y) in cells {
+        mask[x][y] = true;
+    }
+
+    mask
+}
+
+/// Builds a selection from a lasso path given in cell units. A cell is
+/// inside when a ray from its center crosses the path an odd number of
+/// times, so self-intersecting paths select alternating regions.
+pub fn lasso(points: &[Vec2], width: usize, height: usize) -> Option<Mask> {
+    if points.len() < 3 {
+        return None;
+    }
+
+    let mut mask = vec![vec![false; height]; width];
+    for (x, column) in mask.iter_mut().enumerate() {
+        for (y, selected) in column.iter_mut().enumerate() {
+            let center = vec2(x as f32 + 0.5, y as f32 + 0.5);
+            let mut inside = false;
+            let mut prev = points[points.len() - 1];
+            for &point in points {
+                let crosses = (point.y > center.y) != (prev.y > center.y)
+                    && center.x
+                        < (prev.x - point.x) * (center.y - point.y) / (prev.y - point.y) + point.x;
+                if crosses {
+                    inside = !inside;
+                }
+                prev = point;
+            }
+            *selected = inside;
+        }
+    }
+
+    mask.iter()
+        .flatten()
+        .any(|selected| *selected)
+        .then_some(mask)
+}
+
+/// Softens the edges of a selection, fading selected cells in over
+/// `amount` cells from the nearest unselected one. An `amount` of 0
+/// keeps the edges hard.
+pub fn feather(mask: &Mask, amount: usize) -> Weights {
+    let reach = amount as i32;
+    let unselected = |x: i32, y: i32| {
+        x >= 0
+            && y >= 0
+            && mask
+                .get(x as usize)
+                .and_then(|column| column.get(y as usize))
+                .is_some_and(|selected| !selected)
+    };
+
+    mask.iter()
+        .enumerate()
+        .map(|(x, column)| {
+            column
+                .iter()
+                .enumerate()
+                .map(|(y, selected)| {
+                    if !selected {
+                        return 0.0;
+                    }
+
+                    // Only unselected cells within reach can soften this one
+                    let (x, y) = (x as i32, y as i32);
+                    let mut nearest = amount as f32 + 1.0;
+                    for dx in -reach..=reach {
+                        for dy in -reach..=reach {
+                            if unselected(x + dx, y + dy) {
+                                nearest = nearest.min(((dx * dx + dy * dy) as f32).sqrt());
+                            }
+                        }
+                    }
+                    (nearest / (amount as f32 + 1.0)).min(1.0)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Returns the edges between selected and unselected cells as pairs
+/// of cell corners, for drawing the selection's outline.
+pub fn outline(mask: &Mask) -> Vec<(Vec2, Vec2)> {
+    let selected = |x: i32, y: i32| {
+        x >= 0
+            && y >= 0
+            && mask
+                .get(x as usize)
+                .and_then(|column| column.get(y as usize))
+                .copied()
+                .unwrap_or(false)
+    };
+    let mut edges = Vec::new();
+
+    for (x, column) in mask.iter().enumerate() {
+        for (y, _) in column.iter().enumerate().filter(|(_, selected)| **selected) {
+            let (x, y) = (x as i32, y as i32);
+            let (left, right) = (x as f32, x as f32 + 1.0);
+            let (bottom, top) = (y as f32, y as f32 + 1.0);
+            if !selected(x - 1, y) {
+                edges.push((vec2(left, bottom), vec2(left, top)));
+            }
+            if !selected(x + 1, y) {
+                edges.push((vec2(right, bottom), vec2(right, top)));
+            }
+            if !selected(x, y - 1) {
+                edges.push((vec2(left, bottom), vec2(right, bottom)));
+            }
+            if !selected(x, y + 1) {
+                edges.push((vec2(left, top), vec2(right, top)));
+            }
+        }
+    }
+
+    edges
+}
@@ -0,0 +1,60 @@
+use nannou::prelude::*;
+
+use crate::canvas::Pixel;
+
+/// How many cells across and tall a grid must be to hold a `width`
+/// by `height` grid turned by `degrees` without cutting off its
+/// corners.
+pub fn rotated_size(width: usize, height: usize, degrees: f32) -> (usize, usize) {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (sin, cos) = (sin.abs(), cos.abs());
+    let (width, height) = (width as f32, height as f32);
+    // Leave room for rounding so quarter turns keep their size
+    let fit = |size: f32| (size - 0.001).ceil() as usize;
+    (
+        fit(width * cos + height * sin),
+        fit(width * sin + height * cos),
+    )
+}
+
+/// Turns the cells counterclockwise about the center of the grid into
+/// a grid `width` by `height` cells. Each cell takes the color of the
+/// source cell nearest to where it came from, or `background` if that
+/// lies outside of the source.
+pub fn rotate(
+    pixels: &[Vec<Pixel>],
+    degrees: f32,
+    width: usize,
+    height: usize,
+    background: Rgb8,
+) -> Vec<Vec<Pixel>> {
+    let source_x = pixels.len() as f32 / 2.0;
+    let source_y = pixels.first().map_or(0, Vec::len) as f32 / 2.0;
+    let (target_x, target_y) = (width as f32 / 2.0, height as f32 / 2.0);
+    let (sin, cos) = (-degrees).to_radians().sin_cos();
+    let sample = |x: usize, y: usize| {
+        let dx = x as f32 + 0.5 - target_x;
+        let dy = y as f32 + 0.5 - target_y;
+        // Rounding from cell centers keeps quarter turns exact
+        let sx = (dx * cos - dy * sin + source_x - 0.5).round();
+        let sy = (dx * sin + dy * cos + source_y - 0.5).round();
+        if sx < 0.0 || sy < 0.0 {
+            return background;
+        }
+        pixels
+            .get(sx as usize)
+            .and_then(|column| column.get(sy as usize))
+            .map_or(background, |pixel| pixel.color)
+    };
+
+    (0..width)
+        .map(|x| {
+            (0..height)
+                .map(|y| Pixel {
+                    color: sample(x, y),
+                    ..Default::default()
+                })
+                .collect()
+        })
+        .collect()
+}
@@ -0,0 +1,57 @@
+use crate::canvas::Pixel;
+use crate::palette;
+
+/// Thresholds for switching cells over in discrete tweens, so
+/// that the change spreads evenly across the canvas.
+const BAYER: [[f32; 4]; 4] = [
+    [0.0, 8.0, 2.0, 10.0],
+    [12.0, 4.0, 14.0, 6.0],
+    [3.0, 11.0, 1.0, 9.0],
+    [15.0, 7.0, 13.0, 5.0],
+];
+
+/// Blends two grids of the same size, where `t` runs from 0.0
+/// (all `a`) to 1.0 (all `b`). Discrete tweens keep every cell
+/// one of its two colors instead of mixing them.
+pub fn tween(a: &[Vec<Pixel>], b: &[Vec<Pixel>], t: f32, discrete: bool) -> Vec<Vec<Pixel>> {
+    a.iter()
+        .zip(b)
+        .enumerate()
+        .map(|(x, (a, b))| {
+            a.iter()
+                .zip(b)
+                .enumerate()
+                .map(|(y, (a, b))| {
+                    let color = if discrete {
+                        let threshold = (BAYER[x % 4][y % 4] + 0.5) / 16.0;
+                        if t < threshold {
+                            a.color
+                        } else {
+                            b.color
+                        }
+                    } else {
+                        palette::lerp(a.color, b.color, t)
+                    };
+                    Pixel {
+                        color,
+                        x: a.x,
+                        y: a.y,
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Returns the `count` grids evenly spaced between `a` and `b`,
+/// leaving out the two grids themselves.
+pub fn in_betweens(
+    a: &[Vec<Pixel>],
+    b: &[Vec<Pixel>],
+    count: usize,
+    discrete: bool,
+) -> Vec<Vec<Vec<Pixel>>> {
+    (1..=count)
+        .map(|i| tween(a, b, i as f32 / (count + 1) as f32, discrete))
+        .collect()
+}